@@ -0,0 +1,69 @@
+//! Echo replies received per second over loopback with `Config::recv_batch_size` 1, 16
+//! and 64. Needs the permission to open an ICMP socket: `cargo bench --bench recv_batch`.
+//!
+//! Each round sends a burst of requests before waiting for their replies, so that they
+//! queue up on the socket and the receive task may read them in batches.
+
+use std::num::NonZeroU16;
+use std::time::{Duration, Instant};
+
+use futures::future::join_all;
+use surge_ping::{Client, Config, PingIdentifier, PingSequence};
+use tokio::time;
+
+const BATCH_SIZES: [usize; 3] = [1, 16, 64];
+const BURST: u16 = 1_000;
+const ROUNDS: u32 = 50;
+const REPLY_TIMEOUT: Duration = Duration::from_secs(1);
+
+async fn run(batch_size: usize) {
+    let config = Config::builder()
+        .recv_batch_size(batch_size)
+        .recv_buffer_size(4 << 20)
+        .build();
+    let client = match Client::new(&config) {
+        Ok(client) => client,
+        Err(err) => {
+            eprintln!("cannot open an ICMP socket: {err}");
+            return;
+        }
+    };
+    let pinger = client
+        .pinger("127.0.0.1".parse().unwrap(), PingIdentifier(1))
+        .await;
+    let payload = [0; 56];
+
+    let mut received = 0;
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        let mut waiters = Vec::with_capacity(BURST.into());
+        for seq in 1..=BURST {
+            let seq = PingSequence(NonZeroU16::new(seq).unwrap());
+            let (_, waiter) = pinger.ping_send(seq, &payload).await.unwrap();
+            waiters.push(time::timeout(REPLY_TIMEOUT, waiter));
+        }
+        received += join_all(waiters)
+            .await
+            .into_iter()
+            .filter(|reply| matches!(reply, Ok(Ok(_))))
+            .count();
+    }
+    let elapsed = start.elapsed();
+    let sent = u32::from(BURST) * ROUNDS;
+    println!(
+        "recv_batch_size={batch_size}: {received}/{sent} replies in {elapsed:.2?}, {:.0} packets/s",
+        received as f64 / elapsed.as_secs_f64()
+    );
+}
+
+fn main() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        for batch_size in BATCH_SIZES {
+            run(batch_size).await;
+        }
+    });
+}
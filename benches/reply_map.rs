@@ -0,0 +1,69 @@
+//! Reply map throughput with 10k concurrent waiters, with a single lock and with the
+//! sharded map: `cargo bench --bench reply_map`. Each task registers, answers and removes
+//! waiters for its own host, as a pinger of a large sweep and the receive task do.
+
+use std::net::IpAddr;
+use std::num::NonZeroU16;
+use std::sync::Arc;
+use std::time::Instant;
+
+use surge_ping::{PingSequence, ReplyMapBench};
+use tokio::sync::Barrier;
+
+const TASKS: u32 = 10_000;
+const CYCLES: u16 = 100;
+/// Every so many cycles a waiter is given up on instead of answered.
+const ABANDON_EVERY: u16 = 4;
+const SHARDS: [usize; 2] = [1, 16];
+
+async fn run(shards: usize) {
+    let reply_map = ReplyMapBench::new(shards);
+    // The tasks all start together, so that the 10k waiters are outstanding at once.
+    let barrier = Arc::new(Barrier::new(TASKS as usize + 1));
+    let tasks: Vec<_> = (0..TASKS)
+        .map(|task| {
+            let reply_map = reply_map.clone();
+            let barrier = barrier.clone();
+            tokio::spawn(async move {
+                let host = IpAddr::from((0x0a00_0000 + task).to_be_bytes());
+                barrier.wait().await;
+                for cycle in 1..=CYCLES {
+                    let seq = PingSequence(NonZeroU16::new(cycle).unwrap());
+                    if cycle % ABANDON_EVERY == 0 {
+                        reply_map.abandon(host, seq).unwrap();
+                    } else {
+                        reply_map.answer(host, seq).await.unwrap();
+                    }
+                    tokio::task::yield_now().await;
+                }
+            })
+        })
+        .collect();
+    barrier.wait().await;
+    let start = Instant::now();
+    for task in tasks {
+        task.await.unwrap();
+    }
+    let elapsed = start.elapsed();
+    assert!(reply_map.is_empty());
+    let cycles = f64::from(TASKS) * f64::from(CYCLES);
+    println!(
+        "shards={shards}: {cycles} waiters in {elapsed:.2?}, {:.0} waiters/s",
+        cycles / elapsed.as_secs_f64()
+    );
+}
+
+fn main() {
+    let threads = std::thread::available_parallelism().map_or(4, |threads| threads.get().max(4));
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(threads)
+        .enable_all()
+        .build()
+        .unwrap();
+    println!("{TASKS} tasks on {threads} worker threads");
+    runtime.block_on(async {
+        for shards in SHARDS {
+            run(shards).await;
+        }
+    });
+}
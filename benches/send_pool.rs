@@ -0,0 +1,73 @@
+//! Allocations per echo request sent at 50k pps, with and without `Config::send_pool`.
+//! Needs the permission to open an ICMP socket: `cargo bench --bench send_pool`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::num::NonZeroU16;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use surge_ping::{Client, Config, PingIdentifier, PingSequence};
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const PPS: u32 = 50_000;
+const PACKETS: u16 = 50_000;
+
+async fn run(send_pool: bool) {
+    let config = Config::builder()
+        .send_pool(send_pool)
+        .max_pps(PPS)
+        .manual_recv(true)
+        .build();
+    let client = match Client::new(&config) {
+        Ok(client) => client,
+        Err(err) => {
+            eprintln!("cannot open an ICMP socket: {err}");
+            return;
+        }
+    };
+    let pinger = client
+        .pinger("127.0.0.1".parse().unwrap(), PingIdentifier(1))
+        .await;
+    let payload = [0; 56];
+
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for seq in 1..=PACKETS {
+        let seq = PingSequence(NonZeroU16::new(seq).unwrap());
+        pinger.send_ping(seq, &payload).await.unwrap();
+    }
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    println!(
+        "send_pool={send_pool}: {PACKETS} packets in {elapsed:.2?}, {allocations} allocations ({:.2} per packet)",
+        allocations as f64 / f64::from(PACKETS)
+    );
+}
+
+fn main() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        run(false).await;
+        run(true).await;
+    });
+}
@@ -4,25 +4,52 @@ use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, RawFd};
 use std::os::windows::io::{AsRawSocket, FromRawSocket, IntoRawSocket, RawSocket};
 
 use std::{
-    collections::HashMap,
-    io,
-    net::{IpAddr, SocketAddr},
-    sync::Arc,
-    time::Instant,
+    collections::{
+        hash_map::{DefaultHasher, Entry},
+        HashMap, VecDeque,
+    },
+    future::Future,
+    hash::{Hash, Hasher},
+    io, mem,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    num::NonZeroU16,
+    ops::ControlFlow,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime},
 };
 
+use futures::{future, stream, Stream, StreamExt};
+use ipnet::IpNet;
 use parking_lot::Mutex;
-use socket2::{Domain, Protocol, Socket, Type as SockType};
+use rand::random;
+use socket2::{Domain, Protocol, SockAddr, SockRef, Socket, Type as SockType};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use tokio::io::Interest;
 use tokio::{
     net::UdpSocket,
-    sync::oneshot,
+    sync::{
+        mpsc::{self, UnboundedReceiver, UnboundedSender},
+        oneshot::{self, error::TryRecvError},
+    },
     task::{self, JoinHandle},
+    time,
 };
 
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use crate::sys;
 use crate::{
-    config::Config,
-    icmp::{icmpv4::Icmpv4Packet, icmpv6::Icmpv6Packet},
-    IcmpPacket, PingIdentifier, PingSequence, Pinger, SurgeError, ICMP,
+    config::{Config, IdentifierStrategy, MatchPolicy},
+    icmp::{
+        icmpv4::{self, Icmpv4Packet},
+        icmpv6::{self, Icmpv6Packet},
+    },
+    ping::PingOptions,
+    IcmpPacket, MalformedPacketError, PingIdentifier, PingSequence, Pinger, SurgeError, ICMP,
 };
 
 // Check, if the platform's socket operates with ICMP packets in a casual way
@@ -40,31 +67,338 @@ macro_rules! is_linux_icmp_socket {
     };
 }
 
+#[cfg(any(
+    target_os = "android",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "fuchsia",
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "netbsd",
+    target_os = "openbsd",
+))]
+fn set_tclass_v6(socket: &Socket, tclass: u8) -> io::Result<()> {
+    socket.set_tclass_v6(tclass.into())
+}
+
+#[cfg(not(any(
+    target_os = "android",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "fuchsia",
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "netbsd",
+    target_os = "openbsd",
+)))]
+fn set_tclass_v6(_socket: &Socket, _tclass: u8) -> io::Result<()> {
+    Err(unsupported("IPV6_TCLASS"))
+}
+
+/// The IPv6 flow label is 20 bits long.
+const MAX_FLOW_LABEL: u32 = 0xf_ffff;
+
+/// Error returned when a configured socket option does not exist on the target platform.
+#[allow(dead_code)]
+fn unsupported(option: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("{option} is not supported on this platform"),
+    )
+}
+
 #[derive(Clone)]
 pub struct AsyncSocket {
     inner: Arc<UdpSocket>,
     sock_type: SockType,
+    /// A connected UDP socket standing in for an ICMP socket, see [`test_transport`](Self::test_transport).
+    test_transport: bool,
+    mtu_check: Option<MtuCheck>,
+}
+
+/// Size limit of the packets sent, see `Config::builder().enforce_mtu()`.
+#[derive(Clone, Copy)]
+struct MtuCheck {
+    mtu: usize,
+    /// IP header in front of the ICMP message, options included.
+    ip_header_len: usize,
 }
 
 impl AsyncSocket {
     pub fn new(config: &Config) -> io::Result<Self> {
+        if let Some(flow_label) = config.flow_label.filter(|&label| label > MAX_FLOW_LABEL) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("flow label {flow_label:#x} does not fit in 20 bits"),
+            ));
+        }
         let (sock_type, socket) = Self::create_socket(config)?;
 
         socket.set_nonblocking(true)?;
-        if let Some(sock_addr) = &config.bind {
-            socket.bind(sock_addr)?;
+        // Before binding, for the address to be shared.
+        if config.reuse_address {
+            socket.set_reuse_address(true)?;
+        }
+        if config.reuse_port {
+            #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+            socket.set_reuse_port(true)?;
+            #[cfg(not(all(unix, not(any(target_os = "solaris", target_os = "illumos")))))]
+            return Err(unsupported("SO_REUSEPORT"));
+        }
+        match (&config.bind, config.bind_port) {
+            (bind, Some(port)) => {
+                let mut addr = bind
+                    .as_ref()
+                    .and_then(SockAddr::as_socket)
+                    .unwrap_or_else(|| match config.kind {
+                        ICMP::V4 => (Ipv4Addr::UNSPECIFIED, 0).into(),
+                        ICMP::V6 => (Ipv6Addr::UNSPECIFIED, 0).into(),
+                    });
+                addr.set_port(port);
+                socket.bind(&addr.into())?;
+            }
+            (Some(sock_addr), None) => socket.bind(sock_addr)?,
+            (None, None) => {}
         }
         #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
         if let Some(interface) = &config.interface {
             socket.bind_device(Some(interface.as_bytes()))?;
         }
-        if let Some(ttl) = config.ttl {
-            socket.set_ttl(ttl)?;
+        #[cfg(not(any(target_os = "android", target_os = "fuchsia", target_os = "linux")))]
+        if config.interface.is_some() {
+            return Err(unsupported("SO_BINDTODEVICE"));
+        }
+        if config.broadcast {
+            socket.set_broadcast(true)?;
+        }
+        if let Some(priority) = config.priority {
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            sys::set_priority(socket.as_raw_fd(), priority)?;
+            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+            {
+                let _ = priority;
+                return Err(unsupported("SO_PRIORITY"));
+            }
+        }
+        if let Some(timeout) = config.read_timeout {
+            socket.set_read_timeout(Some(timeout))?;
+        }
+        if let Some(size) = config.recv_buffer_size {
+            socket.set_recv_buffer_size(size)?;
+        }
+        if let Some(size) = config.send_buffer_size {
+            socket.set_send_buffer_size(size)?;
+        }
+        #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+        if let Some(fwmark) = config.fwmark {
+            socket.set_mark(fwmark)?;
+        }
+        #[cfg(not(any(target_os = "android", target_os = "fuchsia", target_os = "linux")))]
+        if config.fwmark.is_some() {
+            return Err(unsupported("SO_MARK"));
+        }
+        match config.kind {
+            ICMP::V4 => {
+                if let Some(ttl) = config.ttl {
+                    socket.set_ttl(ttl)?;
+                }
+                if let Some(tos) = config.tos {
+                    socket.set_tos(tos.into())?;
+                }
+                if let Some(hops) = config.multicast_hops {
+                    socket.set_multicast_ttl_v4(hops)?;
+                }
+            }
+            ICMP::V6 => {
+                if let Some(hop_limit) = config.hop_limit {
+                    socket.set_unicast_hops_v6(hop_limit)?;
+                }
+                if let Some(tclass) = config.tos {
+                    set_tclass_v6(&socket, tclass)?;
+                }
+                if let Some(hops) = config.multicast_hops {
+                    socket.set_multicast_hops_v6(hops)?;
+                }
+                if let Some(interface) = config.multicast_interface {
+                    socket.set_multicast_if_v6(interface)?;
+                }
+                if let Some(flow_label) = config.flow_label {
+                    #[cfg(any(target_os = "linux", target_os = "android"))]
+                    sys::set_flow_label(socket.as_raw_fd(), flow_label)?;
+                    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+                    {
+                        let _ = flow_label;
+                        return Err(unsupported("IPV6_FLOWINFO_SEND"));
+                    }
+                }
+            }
+        }
+        if let Some(dont_fragment) = config.dont_fragment {
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            match config.kind {
+                ICMP::V4 => sys::set_dont_fragment_v4(socket.as_raw_fd(), dont_fragment)?,
+                ICMP::V6 => sys::set_dont_fragment_v6(socket.as_raw_fd(), dont_fragment)?,
+            }
+            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+            {
+                let _ = dont_fragment;
+                return Err(unsupported("Don't Fragment"));
+            }
+        }
+        // The IP header is not delivered to us on Linux ICMP sockets, nor on any IPv6 socket,
+        // so the TTL / hop limit has to be requested as ancillary data.
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        match config.kind {
+            ICMP::V4 if is_linux_icmp_socket!(sock_type) => sys::set_recv_ttl(socket.as_raw_fd())?,
+            ICMP::V6 => sys::set_recv_hop_limit(socket.as_raw_fd())?,
+            _ => {}
+        }
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        if config.hardware_timestamps && !sys::set_recv_timestamps(socket.as_raw_fd()) {
+            #[cfg(feature = "tracing")]
+            tracing::warn!("kernel timestamps unavailable, falling back to software timestamps");
+        }
+        // The destination address is part of the ICMPv6 checksum.
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        if config.verify_checksum {
+            if let ICMP::V6 = config.kind {
+                sys::set_recv_pktinfo_v6(socket.as_raw_fd())?;
+            }
+        }
+        if config.record_route {
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            match (config.kind, sock_type) {
+                (ICMP::V4, SockType::RAW) => {
+                    sys::set_ip_options(socket.as_raw_fd(), &icmpv4::record_route_option())?
+                }
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        "Record Route needs a raw IPv4 socket",
+                    ))
+                }
+            }
+            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+            return Err(unsupported("IP_OPTIONS"));
+        }
+        if let Some(types) = &config.icmp_type_filter {
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            match (config.kind, sock_type) {
+                (ICMP::V4, SockType::RAW) => sys::set_icmp_filter(socket.as_raw_fd(), types)?,
+                (ICMP::V6, SockType::RAW) => sys::set_icmp6_filter(socket.as_raw_fd(), types)?,
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        "ICMP type filtering needs a raw socket",
+                    ))
+                }
+            }
+            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+            {
+                let _ = types;
+                return Err(unsupported("ICMP_FILTER"));
+            }
         }
         #[cfg(target_os = "freebsd")]
         if let Some(fib) = config.fib {
             socket.set_fib(fib)?;
         }
+        let mtu_check = if config.enforce_mtu {
+            Some(Self::mtu_check(&socket, config)?)
+        } else {
+            None
+        };
+        Ok(Self {
+            mtu_check,
+            ..Self::from_std(sock_type, socket)?
+        })
+    }
+
+    /// Query the MTU of the interface the socket is bound to, by name or by address.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn mtu_check(socket: &Socket, config: &Config) -> io::Result<MtuCheck> {
+        let bind_addr = config
+            .bind
+            .as_ref()
+            .and_then(|addr| addr.as_socket())
+            .map(|addr| addr.ip())
+            .filter(|ip| !ip.is_unspecified());
+        let interface = match (&config.interface, bind_addr) {
+            (Some(interface), _) => Some(interface.clone()),
+            (None, Some(ip)) => sys::interface_of(ip)?,
+            (None, None) => None,
+        };
+        let interface = interface.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "enforcing the MTU needs an interface or a local bind address",
+            )
+        })?;
+        let ip_header_len = match config.kind {
+            ICMP::V4 if config.record_route => 20 + 40,
+            ICMP::V4 => 20,
+            ICMP::V6 => 40,
+        };
+        Ok(MtuCheck {
+            mtu: sys::interface_mtu(socket.as_raw_fd(), &interface)?,
+            ip_header_len,
+        })
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    fn mtu_check(_: &Socket, _: &Config) -> io::Result<MtuCheck> {
+        Err(unsupported("SIOCGIFMTU"))
+    }
+
+    /// Check that an ICMP message of `len` bytes fits in the MTU, if it is enforced.
+    pub(crate) fn check_mtu(&self, len: usize) -> Result<(), SurgeError> {
+        match self.mtu_check {
+            Some(MtuCheck { mtu, ip_header_len }) if ip_header_len + len > mtu => {
+                Err(SurgeError::ExceedsMtu {
+                    size: ip_header_len + len,
+                    mtu,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Wrap a socket created and configured by the caller. It must be a `DGRAM` or `RAW`
+    /// socket of the address family of `kind`.
+    pub fn from_socket(socket: Socket, kind: ICMP) -> Result<Self, SurgeError> {
+        let sock_type = socket.r#type()?;
+        let domain = socket.local_addr()?.domain();
+        let (expected_domain, expected_proto) = match kind {
+            ICMP::V4 => (Domain::IPV4, Protocol::ICMPV4),
+            ICMP::V6 => (Domain::IPV6, Protocol::ICMPV6),
+        };
+        if domain != expected_domain || (sock_type != SockType::DGRAM && sock_type != SockType::RAW)
+        {
+            return Err(SurgeError::SocketMismatch { kind });
+        }
+        // Elsewhere the protocol cannot be read back from the socket.
+        #[cfg(any(
+            target_os = "android",
+            target_os = "freebsd",
+            target_os = "fuchsia",
+            target_os = "linux"
+        ))]
+        if socket.protocol()? != Some(expected_proto) {
+            return Err(SurgeError::SocketMismatch { kind });
+        }
+        #[cfg(not(any(
+            target_os = "android",
+            target_os = "freebsd",
+            target_os = "fuchsia",
+            target_os = "linux"
+        )))]
+        let _ = expected_proto;
+
+        socket.set_nonblocking(true)?;
+        Ok(Self::from_std(sock_type, socket)?)
+    }
+
+    fn from_std(sock_type: SockType, socket: Socket) -> io::Result<Self> {
         #[cfg(windows)]
         let socket = UdpSocket::from_std(unsafe {
             std::net::UdpSocket::from_raw_socket(socket.into_raw_socket())
@@ -75,9 +409,55 @@ impl AsyncSocket {
         Ok(Self {
             inner: Arc::new(socket),
             sock_type,
+            test_transport: false,
+            mtu_check: None,
         })
     }
 
+    /// Use a UDP socket connected to a peer which plays the network, to exercise the packet
+    /// codecs and reply matching without the privileges ICMP sockets require, e.g. in CI.
+    ///
+    /// Every request is sent to the peer, whatever the host of the pinger, and the datagrams
+    /// it sends back are handled as those of an ICMP `DGRAM` socket of the platform: on Linux
+    /// bare ICMP messages, whose identifier is ignored. Replies are matched on the address of
+    /// the peer, which must therefore be the host of the pingers.
+    pub fn test_transport(socket: UdpSocket) -> Self {
+        Self {
+            inner: Arc::new(socket),
+            sock_type: SockType::DGRAM,
+            test_transport: true,
+            mtu_check: None,
+        }
+    }
+
+    /// A test transport and the peer it is connected to.
+    #[cfg(test)]
+    pub(crate) async fn test_transport_pair() -> (UdpSocket, Self) {
+        let peer = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        socket.connect(peer.local_addr().unwrap()).await.unwrap();
+        peer.connect(socket.local_addr().unwrap()).await.unwrap();
+        (peer, Self::test_transport(socket))
+    }
+
+    /// A test transport whose peer answers the requests `answer` accepts, given as sent,
+    /// and drops the others.
+    #[cfg(test)]
+    pub(crate) async fn test_echo(answer: impl Fn(&[u8]) -> bool + Send + 'static) -> Self {
+        let (peer, socket) = Self::test_transport_pair().await;
+        task::spawn(async move {
+            let mut buf = [0; 2048];
+            while let Ok(len) = peer.recv(&mut buf).await {
+                if answer(&buf[..len]) {
+                    // Echo request to echo reply, the checksum is not checked.
+                    buf[0] = 0;
+                    let _ = peer.send(&buf[..len]).await;
+                }
+            }
+        });
+        socket
+    }
+
     fn create_socket(config: &Config) -> io::Result<(SockType, Socket)> {
         let (domain, proto) = match config.kind {
             ICMP::V4 => (Domain::IPV4, Some(Protocol::ICMPV4)),
@@ -93,6 +473,14 @@ impl AsyncSocket {
                     SockType::DGRAM
                 };
 
+                #[cfg(feature = "tracing")]
+                tracing::warn!(
+                    %err,
+                    "error opening {:?} type socket, trying {:?}",
+                    config.sock_type_hint,
+                    new_type
+                );
+                #[cfg(not(feature = "tracing"))]
                 eprintln!(
                     "error opening {:?} type socket, trying {:?}: {:?}",
                     config.sock_type_hint, new_type, err
@@ -107,14 +495,119 @@ impl AsyncSocket {
         self.inner.recv_from(buf).await
     }
 
+    /// Receive a datagram together with the TTL / hop limit the kernel reported for it, if any.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub(crate) async fn recv_msg(&self, buf: &mut [u8]) -> io::Result<RecvMsg> {
+        self.inner
+            .async_io(Interest::READABLE, || {
+                sys::recv_msg(self.inner.as_raw_fd(), buf)
+            })
+            .await
+    }
+
+    /// Fill `batch` with the datagrams queued on the socket, waiting for at least one.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub(crate) async fn recv_batch(&self, batch: &mut sys::RecvBatch) -> io::Result<()> {
+        self.inner
+            .async_io(Interest::READABLE, || batch.recv(self.inner.as_raw_fd()))
+            .await
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    pub(crate) async fn recv_msg(&self, buf: &mut [u8]) -> io::Result<RecvMsg> {
+        let (size, addr) = self.inner.recv_from(buf).await?;
+        Ok(RecvMsg {
+            size,
+            addr,
+            hop_limit: None,
+            dst_addr: None,
+            kernel_time: None,
+        })
+    }
+
     pub async fn send_to(&self, buf: &mut [u8], target: &SocketAddr) -> io::Result<usize> {
+        if self.test_transport {
+            return self.inner.send(buf).await;
+        }
         self.inner.send_to(buf, target).await
     }
 
+    /// Send a datagram to `target` with the source address and outgoing interface of
+    /// `pktinfo`, instead of those the routing table would pick. Linux ICMPv6 sockets ignore
+    /// the `IPV6_PKTINFO` control message, IPv6 needs a raw socket.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub(crate) async fn send_to_with_pktinfo(
+        &self,
+        buf: &[u8],
+        target: SocketAddr,
+        pktinfo: PktInfo,
+    ) -> io::Result<usize> {
+        if self.test_transport {
+            return self.inner.send(buf).await;
+        }
+        if target.is_ipv6() && self.sock_type != SockType::RAW {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "IPV6_PKTINFO needs a raw socket",
+            ));
+        }
+        self.inner
+            .async_io(Interest::WRITABLE, || {
+                sys::send_to_with_pktinfo(self.inner.as_raw_fd(), buf, target, pktinfo)
+            })
+            .await
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    pub(crate) async fn send_to_with_pktinfo(
+        &self,
+        _buf: &[u8],
+        _target: SocketAddr,
+        _pktinfo: PktInfo,
+    ) -> io::Result<usize> {
+        Err(unsupported("IP_PKTINFO"))
+    }
+
     pub fn local_addr(&self) -> io::Result<SocketAddr> {
         self.inner.local_addr()
     }
 
+    /// The ICMP version of the socket, given by the family of its local address.
+    pub(crate) fn kind(&self) -> ICMP {
+        match self.local_addr() {
+            Ok(SocketAddr::V6(_)) => ICMP::V6,
+            _ => ICMP::V4,
+        }
+    }
+
+    /// The address packets to `target` are sent from: the bound address, or else the one the
+    /// routing table picks, found by connecting a UDP socket (which sends nothing).
+    pub(crate) fn source_for(&self, target: SocketAddr) -> io::Result<IpAddr> {
+        let local_addr = self.local_addr()?.ip();
+        if !local_addr.is_unspecified() {
+            return Ok(local_addr);
+        }
+        let unspecified: IpAddr = match target {
+            SocketAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+            SocketAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+        };
+        let probe = std::net::UdpSocket::bind((unspecified, 0))?;
+        let mut target = target;
+        target.set_port(9);
+        probe.connect(target)?;
+        Ok(probe.local_addr()?.ip())
+    }
+
+    /// The `SO_RCVBUF` size actually granted by the kernel.
+    pub fn recv_buffer_size(&self) -> io::Result<usize> {
+        SockRef::from(self.inner.as_ref()).recv_buffer_size()
+    }
+
+    /// The `SO_SNDBUF` size actually granted by the kernel.
+    pub fn send_buffer_size(&self) -> io::Result<usize> {
+        SockRef::from(self.inner.as_ref()).send_buffer_size()
+    }
+
     pub fn get_type(&self) -> SockType {
         self.sock_type
     }
@@ -130,136 +623,2042 @@ impl AsyncSocket {
     }
 }
 
-#[derive(PartialEq, Eq, Hash)]
+/// Source address and outgoing interface of a datagram sent with
+/// `AsyncSocket::send_to_with_pktinfo`, left to the routing table when unset.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct PktInfo {
+    pub source: Option<IpAddr>,
+    /// Interface index, 0 if unset.
+    pub if_index: u32,
+}
+
+/// A datagram read by [`AsyncSocket::recv_msg`].
+pub(crate) struct RecvMsg {
+    pub size: usize,
+    pub addr: SocketAddr,
+    /// IPv4 TTL or IPv6 hop limit of the received packet, when the kernel reported it.
+    pub hop_limit: Option<u8>,
+    /// Destination address of the received packet, when the kernel reported it.
+    pub dst_addr: Option<IpAddr>,
+    /// Time the packet was received, when the kernel reported it.
+    pub kernel_time: Option<SystemTime>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 struct ReplyToken(IpAddr, Option<PingIdentifier>, PingSequence);
 
+#[derive(Clone)]
 pub struct Reply {
     pub timestamp: Instant,
     pub packet: IcmpPacket,
 }
 
-#[derive(Clone, Default)]
-pub(crate) struct ReplyMap(Arc<Mutex<HashMap<ReplyToken, oneshot::Sender<Reply>>>>);
+/// The payload a reply must echo back, kept by its waiter.
+pub(crate) enum ExpectedPayload {
+    Owned(Vec<u8>),
+    /// Shares the buffer of the caller instead of copying it, see `Pinger::ping_bytes`.
+    #[cfg(feature = "bytes")]
+    Shared(bytes::Bytes),
+}
+
+impl ExpectedPayload {
+    fn as_slice(&self) -> &[u8] {
+        match self {
+            ExpectedPayload::Owned(payload) => payload,
+            #[cfg(feature = "bytes")]
+            ExpectedPayload::Shared(payload) => payload,
+        }
+    }
+}
+
+/// Someone waiting for a reply, with the payload the reply must echo if it is verified.
+pub(crate) struct Waiter {
+    tx: oneshot::Sender<Result<Reply, SurgeError>>,
+    payload: Option<ExpectedPayload>,
+    created: Instant,
+    /// Tells this waiter apart from those registered for the same request before or after it.
+    generation: u64,
+    /// The other waiters of the same request, see `Config::builder().allow_shared_waiters()`.
+    shared: Vec<Waiter>,
+}
+
+impl Waiter {
+    /// Hand the reply over to the waiter, and a copy to the waiters sharing the request,
+    /// checking the echoed payload if requested.
+    fn resolve(mut self, reply: Reply) {
+        for waiter in mem::take(&mut self.shared) {
+            waiter.resolve(reply.clone());
+        }
+        let reply = match self.payload {
+            Some(expected) if reply.packet.to_error().is_none() => {
+                let got = reply.packet.get_payload();
+                if got == expected.as_slice() {
+                    Ok(reply)
+                } else {
+                    Err(SurgeError::PayloadMismatch {
+                        expected_len: expected.as_slice().len(),
+                        got_len: got.len(),
+                    })
+                }
+            }
+            _ => Ok(reply),
+        };
+        // If send fails the receiving end has closed. Nothing to do.
+        let _ = self.tx.send(reply);
+    }
+
+    /// Fail the request instead of handing a reply over, with an error of `err` for each
+    /// waiter.
+    pub(crate) fn fail(self, err: impl Fn() -> SurgeError) {
+        for waiter in self.shared {
+            let _ = waiter.tx.send(Err(err()));
+        }
+        let _ = self.tx.send(Err(err()));
+    }
+
+    /// The waiters of the request whose `ReplyWaiter` is still there, `None` if none is.
+    fn without_closed(self) -> Option<Waiter> {
+        if self.shared.is_empty() {
+            return (!self.tx.is_closed()).then_some(self);
+        }
+        let Waiter {
+            tx,
+            payload,
+            created,
+            generation,
+            shared,
+        } = self;
+        let mut open = shared.into_iter().filter(|waiter| !waiter.tx.is_closed());
+        let mut first = if tx.is_closed() {
+            open.next()?
+        } else {
+            Waiter {
+                tx,
+                payload,
+                created,
+                generation,
+                shared: Vec::new(),
+            }
+        };
+        first.shared = open.collect();
+        Some(first)
+    }
+}
+
+/// The requests of a pinger whose `ReplyWaiter` has not completed yet, with the generation
+/// of their waiter.
+pub(crate) type Outstanding = Arc<Mutex<HashMap<PingSequence, u64>>>;
+
+/// The receiving end of a request registered with the reply map, resolving to its reply.
+/// Dropped before the reply arrives, e.g. when it loses a `select!`, it removes the request
+/// from the map.
+pub struct ReplyWaiter {
+    rx: oneshot::Receiver<Result<Reply, SurgeError>>,
+    reply_map: ReplyMap,
+    host: IpAddr,
+    ident: Option<PingIdentifier>,
+    seq: PingSequence,
+    generation: u64,
+    /// Where the request is tracked until this completes or is dropped, see `track`.
+    outstanding: Option<Outstanding>,
+}
+
+impl ReplyWaiter {
+    /// Keep the request in `outstanding` until this resolves or is dropped.
+    pub(crate) fn track(mut self, outstanding: &Outstanding) -> Self {
+        outstanding.lock().insert(self.seq, self.generation);
+        self.outstanding = Some(outstanding.clone());
+        self
+    }
+
+    fn untrack(&mut self) {
+        if let Some(outstanding) = self.outstanding.take() {
+            let mut outstanding = outstanding.lock();
+            if outstanding.get(&self.seq) == Some(&self.generation) {
+                outstanding.remove(&self.seq);
+            }
+        }
+    }
+}
+
+impl Future for ReplyWaiter {
+    type Output = Result<Reply, SurgeError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let reply = Pin::new(&mut self.rx)
+            .poll(cx)
+            .map(|reply| reply.unwrap_or(Err(SurgeError::NetworkError)));
+        if reply.is_ready() {
+            self.untrack();
+        }
+        reply
+    }
+}
+
+impl Drop for ReplyWaiter {
+    fn drop(&mut self) {
+        self.untrack();
+        // Once the waiter has been taken out of the map, the sequence number may already
+        // be in use by another request.
+        if let Err(TryRecvError::Empty) = self.rx.try_recv() {
+            self.rx.close();
+            self.reply_map.release(self.host, self.ident, self.seq);
+        }
+    }
+}
+
+/// Number of independently locked sub-maps, so that the recv task and the senders of
+/// unrelated hosts do not all contend on a single lock.
+const REPLY_MAP_SHARDS: usize = 16;
+
+/// Number of answered requests remembered per shard to recognize duplicate replies.
+const ANSWERED_HISTORY: usize = 64;
+
+#[derive(Default)]
+struct Shard {
+    waiters: HashMap<ReplyToken, Waiter>,
+    /// The most recently answered requests, oldest first.
+    answered: VecDeque<ReplyToken>,
+}
+
+type ReplyShard = Mutex<Shard>;
+
+/// Called with the sender address and the packet of every unmatched reply.
+type UnmatchedCallback = Box<dyn Fn(IpAddr, &IcmpPacket) + Send + Sync>;
+
+/// Called with the sender address, the error and the bytes of every malformed packet.
+type MalformedCallback = Box<dyn Fn(IpAddr, &MalformedPacketError, &[u8]) + Send + Sync>;
+
+#[derive(Default)]
+struct ReplyState {
+    duplicate: AtomicU64,
+    unmatched: AtomicU64,
+    malformed: AtomicU64,
+    /// See `Config::builder().read_timeout()`.
+    recv_stalls: AtomicU64,
+    on_unmatched: Mutex<Option<UnmatchedCallback>>,
+    on_malformed: Mutex<Option<MalformedCallback>>,
+    /// Set by `Client::shutdown`, no new waiters are accepted.
+    closed: AtomicBool,
+    /// Generation of the next waiter.
+    generation: AtomicU64,
+}
+
+/// Outcome of `ReplyMap::take_answered`.
+enum Answered {
+    Waiter(Waiter),
+    /// The request was already answered.
+    Duplicate,
+    Unmatched,
+}
+
+/// Receives every echo reply for one `(ident, seq)`, whatever host it comes from.
+type Collectors = Mutex<HashMap<(Option<PingIdentifier>, PingSequence), UnboundedSender<Reply>>>;
+
+/// Waiters sharded by `(host, ident)`; all sequences of one pinger live in the same shard.
+#[derive(Clone)]
+pub(crate) struct ReplyMap {
+    shards: Arc<[ReplyShard]>,
+    state: Arc<ReplyState>,
+    collectors: Arc<Collectors>,
+    match_policy: MatchPolicy,
+    /// See `Config::builder().allow_shared_waiters()`.
+    shared_waiters: bool,
+}
+
+impl Default for ReplyMap {
+    fn default() -> Self {
+        Self::with_shards(REPLY_MAP_SHARDS)
+    }
+}
 
 impl ReplyMap {
+    fn new(match_policy: MatchPolicy, shared_waiters: bool) -> Self {
+        ReplyMap {
+            match_policy,
+            shared_waiters,
+            ..Default::default()
+        }
+    }
+
+    fn with_shards(shards: usize) -> Self {
+        ReplyMap {
+            shards: (0..shards.max(1)).map(|_| ReplyShard::default()).collect(),
+            state: Default::default(),
+            collectors: Default::default(),
+            match_policy: Default::default(),
+            shared_waiters: false,
+        }
+    }
+
+    /// The identifier replies are keyed on under the match policy.
+    fn key(&self, ident: Option<PingIdentifier>) -> Option<PingIdentifier> {
+        match self.match_policy {
+            MatchPolicy::IdentAndSeq => ident,
+            MatchPolicy::SeqOnly => None,
+        }
+    }
+
+    fn shard(&self, host: IpAddr, ident: Option<PingIdentifier>) -> &ReplyShard {
+        let mut hasher = DefaultHasher::new();
+        (host, ident).hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % self.shards.len()]
+    }
+
     /// Register to wait for a reply from host with ident and sequence number.
     /// If there is already someone waiting for this specific reply then an
-    /// error is returned.
+    /// error is returned, unless waiters may be shared. When `payload` is set the reply
+    /// must echo it back.
     pub fn new_waiter(
         &self,
         host: IpAddr,
         ident: Option<PingIdentifier>,
         seq: PingSequence,
-    ) -> Result<oneshot::Receiver<Reply>, SurgeError> {
+        payload: Option<ExpectedPayload>,
+    ) -> Result<ReplyWaiter, SurgeError> {
+        let ident = self.key(ident);
         let (tx, rx) = oneshot::channel();
-        if self
-            .0
-            .lock()
-            .insert(ReplyToken(host, ident, seq), tx)
-            .is_some()
-        {
-            return Err(SurgeError::IdenticalRequests { host, ident, seq });
+        let mut shard = self.shard(host, ident).lock();
+        // Checked under the shard lock so that no waiter slips in once `close` drained the shard.
+        if self.state.closed.load(Ordering::Acquire) {
+            return Err(SurgeError::ClientShutdown);
+        }
+        let generation = self.state.generation.fetch_add(1, Ordering::Relaxed);
+        let waiter = Waiter {
+            tx,
+            payload,
+            created: Instant::now(),
+            generation,
+            shared: Vec::new(),
+        };
+        match shard.waiters.entry(ReplyToken(host, ident, seq)) {
+            Entry::Occupied(mut entry) if self.shared_waiters => {
+                entry.get_mut().shared.push(waiter)
+            }
+            Entry::Occupied(_) => return Err(SurgeError::IdenticalRequests { host, ident, seq }),
+            Entry::Vacant(entry) => {
+                entry.insert(waiter);
+            }
         }
-        Ok(rx)
+        Ok(ReplyWaiter {
+            rx,
+            reply_map: self.clone(),
+            host,
+            ident,
+            seq,
+            generation,
+            outstanding: None,
+        })
     }
 
-    /// Remove a waiter.
+    /// Remove the waiter of `generation`, if it still waits, leaving the other waiters of
+    /// the request alone.
     pub(crate) fn remove(
         &self,
         host: IpAddr,
         ident: Option<PingIdentifier>,
         seq: PingSequence,
-    ) -> Option<oneshot::Sender<Reply>> {
-        self.0.lock().remove(&ReplyToken(host, ident, seq))
+        generation: u64,
+    ) -> Option<Waiter> {
+        let ident = self.key(ident);
+        let token = ReplyToken(host, ident, seq);
+        let mut shard = self.shard(host, ident).lock();
+        let first = shard.waiters.get_mut(&token)?;
+        if first.generation != generation {
+            let index = first
+                .shared
+                .iter()
+                .position(|waiter| waiter.generation == generation)?;
+            return Some(first.shared.remove(index));
+        }
+        let mut waiter = shard.waiters.remove(&token)?;
+        let mut shared = mem::take(&mut waiter.shared).into_iter();
+        if let Some(mut next) = shared.next() {
+            next.shared = shared.collect();
+            shard.waiters.insert(token, next);
+        }
+        Some(waiter)
     }
-}
 
-///
-/// If you want to pass the `Client` in the task, please wrap it with `Arc`: `Arc<Client>`.
-/// and can realize the simultaneous ping of multiple addresses when only one `socket` is created.
-///
-#[derive(Clone)]
-pub struct Client {
-    socket: AsyncSocket,
-    reply_map: ReplyMap,
-    recv: Arc<JoinHandle<()>>,
-}
+    /// Drop the waiters of a request whose `ReplyWaiter` is gone, removing the request if
+    /// none is left.
+    pub(crate) fn release(&self, host: IpAddr, ident: Option<PingIdentifier>, seq: PingSequence) {
+        let ident = self.key(ident);
+        let token = ReplyToken(host, ident, seq);
+        let mut shard = self.shard(host, ident).lock();
+        if let Some(waiter) = shard
+            .waiters
+            .remove(&token)
+            .and_then(Waiter::without_closed)
+        {
+            shard.waiters.insert(token, waiter);
+        }
+    }
 
-impl Drop for Client {
-    fn drop(&mut self) {
-        // The client may pass through multiple tasks, so need to judge whether the number of references is 1.
-        if Arc::strong_count(&self.recv) <= 1 {
-            self.recv.abort();
+    /// Remove the waiter a received reply answers. Replies nobody waits for are counted
+    /// as duplicates if the request was answered recently, as unmatched otherwise.
+    fn take_answered(
+        &self,
+        host: IpAddr,
+        ident: Option<PingIdentifier>,
+        seq: PingSequence,
+    ) -> Answered {
+        match self.take_waiter(host, ident, seq) {
+            Some(waiter) => Answered::Waiter(waiter),
+            None => self.unanswered(host, ident, seq),
         }
     }
-}
 
-impl Client {
-    /// A client is generated according to the configuration. In fact, a `AsyncSocket` is wrapped inside,
-    /// and you can clone to any `task` at will.
-    pub fn new(config: &Config) -> io::Result<Self> {
-        let socket = AsyncSocket::new(config)?;
-        let reply_map = ReplyMap::default();
-        let recv = task::spawn(recv_task(socket.clone(), reply_map.clone()));
-        Ok(Self {
-            socket,
-            reply_map,
-            recv: Arc::new(recv),
-        })
+    /// Remove the waiter a received reply answers, remembering the request as answered.
+    fn take_waiter(
+        &self,
+        host: IpAddr,
+        ident: Option<PingIdentifier>,
+        seq: PingSequence,
+    ) -> Option<Waiter> {
+        let ident = self.key(ident);
+        let token = ReplyToken(host, ident, seq);
+        let mut shard = self.shard(host, ident).lock();
+        let waiter = shard.waiters.remove(&token)?;
+        if shard.answered.len() == ANSWERED_HISTORY {
+            shard.answered.pop_front();
+        }
+        shard.answered.push_back(token);
+        Some(waiter)
     }
 
-    /// Create a `Pinger` instance, you can make special configuration for this instance.
-    pub async fn pinger(&self, host: IpAddr, ident: PingIdentifier) -> Pinger {
-        Pinger::new(host, ident, self.socket.clone(), self.reply_map.clone())
+    /// Count a reply nobody waits for, as a duplicate if the request was answered recently.
+    fn unanswered(
+        &self,
+        host: IpAddr,
+        ident: Option<PingIdentifier>,
+        seq: PingSequence,
+    ) -> Answered {
+        let ident = self.key(ident);
+        let token = ReplyToken(host, ident, seq);
+        let duplicate = self.shard(host, ident).lock().answered.contains(&token);
+        #[cfg(feature = "tracing")]
+        tracing::debug!(%host, ?ident, %seq, duplicate, "dropping unmatched reply");
+        if duplicate {
+            self.state.duplicate.fetch_add(1, Ordering::Relaxed);
+            Answered::Duplicate
+        } else {
+            self.state.unmatched.fetch_add(1, Ordering::Relaxed);
+            Answered::Unmatched
+        }
     }
 
-    /// Expose the underlying socket, if user wants to modify any options on it
-    pub fn get_socket(&self) -> AsyncSocket {
-        self.socket.clone()
+    /// Hand an unmatched reply over to the callback of `Client::on_unmatched`, if any.
+    fn publish_unmatched(&self, from: IpAddr, packet: &IcmpPacket) {
+        if let Some(callback) = &*self.state.on_unmatched.lock() {
+            callback(from, packet);
+        }
     }
-}
 
-async fn recv_task(socket: AsyncSocket, reply_map: ReplyMap) {
-    let mut buf = [0; 2048];
-    loop {
-        if let Ok((sz, addr)) = socket.recv_from(&mut buf).await {
-            let timestamp = Instant::now();
-            let message = &buf[..sz];
-            let local_addr = socket.local_addr().unwrap().ip();
-            let packet = {
-                let result = match addr.ip() {
-                    IpAddr::V4(src_addr) => {
-                        let local_addr_ip4 = match local_addr {
-                            IpAddr::V4(local_addr_ip4) => local_addr_ip4,
-                            _ => continue,
-                        };
-
-                        Icmpv4Packet::decode(message, socket.sock_type, src_addr, local_addr_ip4)
-                            .map(IcmpPacket::V4)
-                    }
-                    IpAddr::V6(src_addr) => {
-                        Icmpv6Packet::decode(message, src_addr).map(IcmpPacket::V6)
-                    }
-                };
-                match result {
-                    Ok(packet) => packet,
-                    Err(err) => {
-                        eprintln!("error decoding ICMP packet: {err:?}");
-                        continue;
+    /// Count a packet which could not be decoded and hand it over to the callback of
+    /// `Client::on_malformed`, if any.
+    fn publish_malformed(&self, from: IpAddr, err: &MalformedPacketError, bytes: &[u8]) {
+        self.state.malformed.fetch_add(1, Ordering::Relaxed);
+        if let Some(callback) = &*self.state.on_malformed.lock() {
+            callback(from, err, bytes);
+        }
+    }
+
+    /// Count and log a `timeout` without a datagram while requests were waiting for their
+    /// reply, see `Config::builder().read_timeout()`.
+    fn publish_stall(&self, timeout: Duration) {
+        self.state.recv_stalls.fetch_add(1, Ordering::Relaxed);
+        let pending = self.len();
+        #[cfg(feature = "tracing")]
+        tracing::warn!(
+            ?timeout,
+            pending,
+            "no datagram received within the read timeout"
+        );
+        #[cfg(not(feature = "tracing"))]
+        eprintln!("no datagram received within {timeout:?}, {pending} requests pending");
+    }
+
+    /// Register to receive all the echo replies with ident and sequence number, from any
+    /// host, which no waiter is registered for, until [`remove_collector`](Self::remove_collector) is called.
+    pub(crate) fn new_collector(
+        &self,
+        host: IpAddr,
+        ident: Option<PingIdentifier>,
+        seq: PingSequence,
+    ) -> Result<UnboundedReceiver<Reply>, SurgeError> {
+        let ident = self.key(ident);
+        if self.state.closed.load(Ordering::Acquire) {
+            return Err(SurgeError::ClientShutdown);
+        }
+        let (tx, rx) = mpsc::unbounded_channel();
+        match self.collectors.lock().entry((ident, seq)) {
+            Entry::Occupied(_) => Err(SurgeError::IdenticalRequests { host, ident, seq }),
+            Entry::Vacant(entry) => {
+                entry.insert(tx);
+                Ok(rx)
+            }
+        }
+    }
+
+    pub(crate) fn remove_collector(&self, ident: Option<PingIdentifier>, seq: PingSequence) {
+        let ident = self.key(ident);
+        self.collectors.lock().remove(&(ident, seq));
+    }
+
+    /// Hand a reply over to the collector of its ident and sequence number, if any.
+    /// The reply is given back if nobody collected it.
+    fn collect(
+        &self,
+        ident: Option<PingIdentifier>,
+        seq: PingSequence,
+        reply: Reply,
+    ) -> Option<Reply> {
+        let ident = self.key(ident);
+        match self.collectors.lock().get(&(ident, seq)) {
+            Some(tx) => tx.send(reply).err().map(|err| err.0),
+            None => Some(reply),
+        }
+    }
+
+    /// Stop accepting new waiters.
+    fn close(&self) {
+        self.state.closed.store(true, Ordering::Release);
+    }
+
+    /// Number of registered waiters, locking each shard in turn.
+    fn len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.lock().waiters.len())
+            .sum()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.shards
+            .iter()
+            .all(|shard| shard.lock().waiters.is_empty())
+    }
+
+    /// Resolve the waiters registered for longer than `ttl` with `SurgeError::Timeout`.
+    fn reap(&self, ttl: Duration) {
+        let now = Instant::now();
+        for shard in self.shards.iter() {
+            let mut shard = shard.lock();
+            let expired: Vec<ReplyToken> = shard
+                .waiters
+                .iter()
+                .filter(|(_, waiter)| now.saturating_duration_since(waiter.created) >= ttl)
+                .map(|(token, _)| *token)
+                .collect();
+            for token in expired {
+                let ReplyToken(host, _, seq) = token;
+                if let Some(waiter) = shard.waiters.remove(&token) {
+                    waiter.fail(|| SurgeError::Timeout { host, seq });
+                }
+            }
+        }
+    }
+
+    /// Resolve every waiter with `SurgeError::ClientShutdown`.
+    fn drain(&self) {
+        for shard in self.shards.iter() {
+            for (_, waiter) in shard.lock().waiters.drain() {
+                waiter.fail(|| SurgeError::ClientShutdown);
+            }
+        }
+    }
+
+    /// Drop every waiter, their receivers resolve with `SurgeError::NetworkError`.
+    pub(crate) fn clear(&self) {
+        for shard in self.shards.iter() {
+            shard.lock().waiters.clear();
+        }
+    }
+}
+
+/// A reply map of `shards` sub-maps driven the way pingers and the receive task drive it,
+/// for `benches/reply_map.rs`. Not part of the API.
+#[doc(hidden)]
+#[derive(Clone)]
+pub struct ReplyMapBench(ReplyMap);
+
+impl ReplyMapBench {
+    pub fn new(shards: usize) -> Self {
+        Self(ReplyMap::with_shards(shards))
+    }
+
+    /// Register a waiter, answer it as the receive task does and wait for the reply.
+    pub async fn answer(&self, host: IpAddr, seq: PingSequence) -> Result<(), SurgeError> {
+        let waiter = self.0.new_waiter(host, None, seq, None)?;
+        if let Some(answered) = self.0.take_waiter(host, None, seq) {
+            answered.resolve(Reply {
+                timestamp: Instant::now(),
+                packet: IcmpPacket::V4(Icmpv4Packet::default()),
+            });
+        }
+        waiter.await.map(drop)
+    }
+
+    /// Register a waiter and give up on it, which removes it.
+    pub fn abandon(&self, host: IpAddr, seq: PingSequence) -> Result<(), SurgeError> {
+        self.0.new_waiter(host, None, seq, None).map(drop)
+    }
+
+    /// Number of registered waiters.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// How often `Client::shutdown` checks whether the in-flight requests are done.
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+///
+/// If you want to pass the `Client` in the task, please wrap it with `Arc`: `Arc<Client>`.
+/// and can realize the simultaneous ping of multiple addresses when only one `socket` is created.
+///
+#[derive(Clone)]
+pub struct Client {
+    socket: AsyncSocket,
+    reply_map: ReplyMap,
+    options: PingOptions,
+    recv_options: RecvOptions,
+    /// The receive task, shared by the clones. `None` with `Config::manual_recv`, or once
+    /// taken by `shutdown`.
+    recv: Arc<Mutex<Option<JoinHandle<()>>>>,
+    reaper: Option<Arc<JoinHandle<()>>>,
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        // The client may pass through multiple tasks, so need to judge whether the number of references is 1.
+        if Arc::strong_count(&self.recv) <= 1 {
+            if let Some(recv) = &*self.recv.lock() {
+                recv.abort();
+            }
+            if let Some(reaper) = &self.reaper {
+                reaper.abort();
+            }
+            // Nobody is left to deliver replies, so wake up the pending waiters.
+            self.reply_map.clear();
+        }
+    }
+}
+
+/// Options of [`Client::ping_list`].
+#[derive(Debug, Clone)]
+pub struct PingListOpts {
+    /// Most hosts pinged at a time. (default: 256)
+    pub concurrency: usize,
+    /// How long to wait for each reply. (default: 1 second)
+    pub timeout: Duration,
+    /// (default: 56 zero bytes, like `ping`)
+    pub payload: Vec<u8>,
+    /// Requests sent again to a host after the first one failed. (default: 0)
+    pub retries: usize,
+}
+
+impl Default for PingListOpts {
+    fn default() -> Self {
+        PingListOpts {
+            concurrency: 256,
+            timeout: Duration::from_secs(1),
+            payload: vec![0; 56],
+            retries: 0,
+        }
+    }
+}
+
+/// The outcome of [`Client::blast`].
+#[derive(Debug, Default)]
+pub struct BlastReport {
+    /// Requests handed to the socket.
+    pub sent: usize,
+    /// The error of each request which could not be sent.
+    pub errors: Vec<SurgeError>,
+}
+
+/// The kind of ICMP socket of a client, see [`Client::socket_mode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketMode {
+    /// The client builds the whole ICMP message, identifier included, and receives every
+    /// ICMP message the host gets. Datagram ICMP sockets of platforms other than Linux and
+    /// Android behave this way too.
+    Raw,
+    /// A Linux or Android datagram ICMP socket: the kernel replaces the identifier with the
+    /// port of the socket and only delivers the replies to its own requests, so
+    /// [`Pinger::ident`] is `None`.
+    DatagramIcmp,
+}
+
+/// The kinds of ICMP sockets the process may open, see [`Client::probe_support`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SupportedModes {
+    /// Raw IPv4 sockets, which need root or `CAP_NET_RAW`.
+    pub raw_v4: bool,
+    /// Datagram IPv4 sockets, e.g. on Linux for the groups of `net.ipv4.ping_group_range`.
+    pub dgram_v4: bool,
+    pub raw_v6: bool,
+    pub dgram_v6: bool,
+}
+
+impl SupportedModes {
+    /// Whether a `Client` of this kind can be created, with either socket type.
+    pub fn any(&self, kind: ICMP) -> bool {
+        match kind {
+            ICMP::V4 => self.raw_v4 || self.dgram_v4,
+            ICMP::V6 => self.raw_v6 || self.dgram_v6,
+        }
+    }
+}
+
+/// The error of `AsyncSocket::new`, telling a missing privilege apart: the socket could not
+/// be created and no other ICMP socket type is allowed either.
+fn socket_error(err: io::Error, kind: ICMP) -> SurgeError {
+    if err.kind() == io::ErrorKind::PermissionDenied && !Client::probe_support().any(kind) {
+        SurgeError::InsufficientPrivileges { kind, source: err }
+    } else {
+        err.into()
+    }
+}
+
+impl Client {
+    /// Maximum number of requests in flight in [`ping_many`](Self::ping_many).
+    pub const PING_MANY_CONCURRENCY: usize = 1024;
+
+    /// Largest network [`sweep_cidr`](Self::sweep_cidr) accepts, in addresses: an IPv4
+    /// `/16` or an IPv6 `/112`.
+    pub const SWEEP_MAX_HOSTS: u32 = 1 << 16;
+
+    /// A client is generated according to the configuration. In fact, a `AsyncSocket` is wrapped inside,
+    /// and you can clone to any `task` at will.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`SurgeError::InsufficientPrivileges`] if the process may open no ICMP socket
+    /// at all, or [`SurgeError::IOError`] if the socket cannot be created or one of the
+    /// configured options is rejected by the system.
+    pub fn new(config: &Config) -> Result<Self, SurgeError> {
+        let socket = AsyncSocket::new(config).map_err(|err| socket_error(err, config.kind))?;
+        Ok(Self::with_socket(socket, config))
+    }
+
+    /// A client whose pingers all use `ident`, whatever identifier they are given, e.g. one
+    /// allocated by a coordinator so that processes sharing the host never collide. Replies
+    /// are matched on the identifier as well as the sequence number, overriding
+    /// [`MatchPolicy::SeqOnly`], so that a reply carrying another identifier is counted as
+    /// unmatched instead of being handed over.
+    ///
+    /// Linux ICMP (`DGRAM`) sockets ignore `ident`: the kernel sets the identifier to the
+    /// port of the socket and only delivers the replies carrying it, see
+    /// `Config::builder().bind_port()`.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`new`](Self::new).
+    pub fn with_identifier(config: &Config, ident: PingIdentifier) -> Result<Self, SurgeError> {
+        let socket = AsyncSocket::new(config).map_err(|err| socket_error(err, config.kind))?;
+        let mut options = PingOptions::new(config);
+        options.identifier_strategy = Some(IdentifierStrategy::Fixed(ident));
+        Ok(Self::with_options(
+            socket,
+            config,
+            MatchPolicy::IdentAndSeq,
+            options,
+        ))
+    }
+
+    /// Which kinds of ICMP sockets this process may open, e.g. to pick a `sock_type_hint` or
+    /// to report a missing privilege up front.
+    pub fn probe_support() -> SupportedModes {
+        let usable = |domain, sock_type, proto| Socket::new(domain, sock_type, Some(proto)).is_ok();
+        SupportedModes {
+            raw_v4: usable(Domain::IPV4, SockType::RAW, Protocol::ICMPV4),
+            dgram_v4: usable(Domain::IPV4, SockType::DGRAM, Protocol::ICMPV4),
+            raw_v6: usable(Domain::IPV6, SockType::RAW, Protocol::ICMPV6),
+            dgram_v6: usable(Domain::IPV6, SockType::DGRAM, Protocol::ICMPV6),
+        }
+    }
+
+    /// A client using a socket created and configured by the caller, for options the
+    /// [`Config`] does not cover. The socket options of the configuration are not applied.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`SurgeError::SocketMismatch`] if `socket` is not a `DGRAM` or `RAW` socket
+    /// of the address family of `kind`.
+    pub fn from_socket(socket: Socket, kind: ICMP) -> Result<Self, SurgeError> {
+        let socket = AsyncSocket::from_socket(socket, kind)?;
+        Ok(Self::with_socket(
+            socket,
+            &Config::builder().kind(kind).build(),
+        ))
+    }
+
+    /// A client over a UDP socket connected to a test peer, see [`AsyncSocket::test_transport`].
+    pub fn from_test_transport(socket: UdpSocket) -> Self {
+        Self::with_socket(AsyncSocket::test_transport(socket), &Config::default())
+    }
+
+    /// A client over the test transport and the peer it is connected to.
+    #[cfg(test)]
+    pub(crate) async fn test_transport_pair() -> (UdpSocket, Self) {
+        let (peer, socket) = AsyncSocket::test_transport_pair().await;
+        (peer, Self::with_socket(socket, &Config::default()))
+    }
+
+    /// A client over the test transport whose peer answers the requests `answer` accepts,
+    /// see [`AsyncSocket::test_echo`].
+    #[cfg(test)]
+    pub(crate) async fn test_echo(answer: impl Fn(&[u8]) -> bool + Send + 'static) -> Self {
+        Self::with_socket(AsyncSocket::test_echo(answer).await, &Config::default())
+    }
+
+    fn with_socket(socket: AsyncSocket, config: &Config) -> Self {
+        Self::with_options(
+            socket,
+            config,
+            config.match_policy,
+            PingOptions::new(config),
+        )
+    }
+
+    fn with_options(
+        socket: AsyncSocket,
+        config: &Config,
+        match_policy: MatchPolicy,
+        options: PingOptions,
+    ) -> Self {
+        let reply_map = ReplyMap::new(match_policy, config.allow_shared_waiters);
+        let recv_options = RecvOptions::new(config);
+        let recv = (!config.manual_recv)
+            .then(|| task::spawn(recv_task(socket.clone(), reply_map.clone(), recv_options)));
+        let reaper = config
+            .waiter_ttl
+            .map(|ttl| Arc::new(task::spawn(reap_task(reply_map.clone(), ttl))));
+        Self {
+            socket,
+            reply_map,
+            options,
+            recv_options,
+            recv: Arc::new(Mutex::new(recv)),
+            reaper,
+        }
+    }
+
+    /// Receive one datagram and hand the reply it carries over to the request waiting for
+    /// it, for clients created with `Config::builder().manual_recv(true)`, which must call
+    /// it in a loop for their pings to be answered. Waits until a datagram is readable.
+    ///
+    /// Undecodable or unmatched datagrams are dropped like by the receive task, they are
+    /// not reported as errors.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`SurgeError::IOError`] if reading the socket fails.
+    pub async fn recv_once(&self) -> Result<(), SurgeError> {
+        let mut buf = [0; 2048];
+        let msg = self.socket.recv_msg(&mut buf).await?;
+        let timestamp = Instant::now();
+        dispatch_reply(
+            &self.socket,
+            &self.reply_map,
+            self.recv_options,
+            &msg,
+            &buf[..msg.size],
+            timestamp,
+        );
+        Ok(())
+    }
+
+    /// Create a `Pinger` instance, you can make special configuration for this instance.
+    ///
+    /// `ident` is overridden by `Config::builder().identifier_strategy()` if set.
+    ///
+    /// If `host` is not of the family of the client, e.g. an IPv6 host and an ICMP `V4`
+    /// client, each request fails with [`SurgeError::AddressFamilyMismatch`]; use
+    /// [`try_pinger`](Self::try_pinger) to find out here instead.
+    pub async fn pinger(&self, host: IpAddr, ident: PingIdentifier) -> Pinger {
+        Pinger::new(
+            host,
+            self.options.identifier(ident),
+            self.socket.clone(),
+            self.reply_map.clone(),
+            self.options.clone(),
+        )
+    }
+
+    /// Create a `Pinger` instance like [`pinger`](Self::pinger), failing with
+    /// [`SurgeError::AddressFamilyMismatch`] if `host` is not of the family of the client.
+    pub async fn try_pinger(
+        &self,
+        host: IpAddr,
+        ident: PingIdentifier,
+    ) -> Result<Pinger, SurgeError> {
+        let pinger = self.pinger(host, ident).await;
+        pinger.check_family()?;
+        Ok(pinger)
+    }
+
+    /// Ping every host of `targets` once, sharing this client's socket and a common random
+    /// identifier, waiting at most `timeout` for each reply.
+    ///
+    /// At most [`PING_MANY_CONCURRENCY`](Self::PING_MANY_CONCURRENCY) requests are in flight
+    /// at a time. Results are returned in the order they complete.
+    pub async fn ping_many(
+        &self,
+        targets: &[IpAddr],
+        payload: &[u8],
+        timeout: Duration,
+    ) -> Vec<(IpAddr, Result<(IcmpPacket, Duration), SurgeError>)> {
+        let ident = PingIdentifier(random());
+        let seq = PingSequence(NonZeroU16::MIN);
+        stream::iter(targets)
+            .map(|&host| async move {
+                let pinger = self.pinger(host, ident).await;
+                (host, pinger.ping_timeout(seq, payload, timeout).await)
+            })
+            .buffer_unordered(Self::PING_MANY_CONCURRENCY)
+            .collect()
+            .await
+    }
+
+    /// Ping every host address of `cidr` once, like [`ping_many`](Self::ping_many) with at
+    /// most `max_concurrency` requests in flight, and report which ones answered. The
+    /// network and broadcast addresses of IPv4 networks are skipped. Results are returned
+    /// in address order.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`SurgeError::NetworkTooLarge`] if `cidr` has more than
+    /// [`SWEEP_MAX_HOSTS`](Self::SWEEP_MAX_HOSTS) addresses, before pinging anything.
+    pub async fn sweep_cidr(
+        &self,
+        cidr: IpNet,
+        payload: &[u8],
+        timeout: Duration,
+        max_concurrency: usize,
+    ) -> Result<Vec<(IpAddr, bool)>, SurgeError> {
+        let host_bits = cidr.max_prefix_len() - cidr.prefix_len();
+        if host_bits > Self::SWEEP_MAX_HOSTS.trailing_zeros() as u8 {
+            return Err(SurgeError::NetworkTooLarge {
+                cidr,
+                max: Self::SWEEP_MAX_HOSTS,
+            });
+        }
+        let ident = PingIdentifier(random());
+        let seq = PingSequence(NonZeroU16::MIN);
+        let results = stream::iter(cidr.hosts())
+            .map(|host| async move {
+                let pinger = self.pinger(host, ident).await;
+                (
+                    host,
+                    pinger.ping_timeout(seq, payload, timeout).await.is_ok(),
+                )
+            })
+            .buffered(max_concurrency.max(1))
+            .collect()
+            .await;
+        Ok(results)
+    }
+
+    /// Ping every host of `targets` once, retrying each failed request up to
+    /// `opts.retries` times with the next sequence number, and yield the result of each
+    /// host as it completes. At most `opts.concurrency` hosts are pinged at a time, and
+    /// targets are only pulled from `targets` as room frees up.
+    pub fn ping_list<'a, I>(
+        &'a self,
+        targets: I,
+        opts: PingListOpts,
+    ) -> impl Stream<Item = (IpAddr, Result<Duration, SurgeError>)> + 'a
+    where
+        I: IntoIterator<Item = IpAddr>,
+        I::IntoIter: 'a,
+    {
+        let ident = PingIdentifier(random());
+        let concurrency = opts.concurrency.max(1);
+        let opts = Arc::new(opts);
+        stream::iter(targets)
+            .map(move |host| {
+                let opts = opts.clone();
+                async move {
+                    let pinger = self.pinger(host, ident).await;
+                    let mut seq = PingSequence(NonZeroU16::MIN);
+                    let mut retries = opts.retries;
+                    loop {
+                        match pinger.ping_timeout(seq, &opts.payload, opts.timeout).await {
+                            Ok((_, rtt)) => return (host, Ok(rtt)),
+                            Err(_) if retries > 0 => {
+                                retries -= 1;
+                                seq = seq.next();
+                            }
+                            Err(err) => return (host, Err(err)),
+                        }
                     }
                 }
-            };
+            })
+            .buffer_unordered(concurrency)
+    }
+
+    /// Send `count` echo requests of `size` bytes of payload to `target` at `pps` packets
+    /// per second, as fast as possible if 0, without waiting for any reply: no reply waiter
+    /// is registered, so the replies are only counted as unmatched. Meant to measure how
+    /// fast requests can be sent.
+    ///
+    /// Send errors do not stop the blast, they are returned in the report. Fails right away
+    /// with [`SurgeError::AddressFamilyMismatch`] if `target` is not of the family of the
+    /// client.
+    pub async fn blast(
+        &self,
+        target: IpAddr,
+        count: usize,
+        size: usize,
+        pps: u32,
+    ) -> Result<BlastReport, SurgeError> {
+        let pinger = self.try_pinger(target, PingIdentifier(random())).await?;
+        let payload = vec![0; size];
+        let mut ticker = (pps > 0).then(|| time::interval(Duration::from_secs(1) / pps));
+        let mut seq = PingSequence(NonZeroU16::MIN);
+        let mut report = BlastReport::default();
+        for _ in 0..count {
+            if let Some(ticker) = &mut ticker {
+                ticker.tick().await;
+            }
+            match pinger.send_ping(seq, &payload).await {
+                Ok(()) => report.sent += 1,
+                Err(err) => report.errors.push(err),
+            }
+            seq = seq.next();
+        }
+        Ok(report)
+    }
+
+    /// Shut the client down gracefully.
+    ///
+    /// New requests, from this client or any of its clones and pingers, fail with
+    /// [`SurgeError::ClientShutdown`] right away. Requests already in flight get up to
+    /// `grace` to be answered or to time out, after which they also resolve with
+    /// `ClientShutdown`. Finally the receive task is stopped and awaited.
+    ///
+    /// The receive task is the one of every clone: clones still in use afterwards can
+    /// neither send requests nor receive replies, and are only good for being dropped.
+    pub async fn shutdown(self, grace: Duration) {
+        self.reply_map.close();
+        let deadline = time::Instant::now() + grace;
+        while !self.reply_map.is_empty() && time::Instant::now() < deadline {
+            time::sleep(SHUTDOWN_POLL_INTERVAL.min(grace)).await;
+        }
+        self.reply_map.drain();
+
+        if let Some(reaper) = &self.reaper {
+            reaper.abort();
+        }
+        let recv = self.recv.lock().take();
+        if let Some(recv) = recv {
+            recv.abort();
+            // Cancelled, unless it had already stopped on its own.
+            let _ = recv.await;
+        }
+    }
+
+    /// Number of requests waiting for their reply, across all the pingers of this client.
+    ///
+    /// A count that keeps growing points to requests that are never timed out.
+    pub fn pending_count(&self) -> usize {
+        self.reply_map.len()
+    }
+
+    /// Number of replies received for a request which had already been answered, what
+    /// `ping` reports as `(DUP!)`. Only recently answered requests are remembered, older
+    /// duplicates are counted as [`unmatched_replies`](Self::unmatched_replies).
+    pub fn duplicate_replies(&self) -> u64 {
+        self.reply_map.state.duplicate.load(Ordering::Relaxed)
+    }
+
+    /// Number of replies received that no request was waiting for: late replies to
+    /// requests that timed out, and on raw sockets, replies to other processes' pings.
+    pub fn unmatched_replies(&self) -> u64 {
+        self.reply_map.state.unmatched.load(Ordering::Relaxed)
+    }
+
+    /// Call `callback` with the sender address and the packet of every unmatched reply,
+    /// see [`unmatched_replies`](Self::unmatched_replies), instead of dropping them silently.
+    /// Useful to find out why expected replies go missing, e.g. identifiers which do not
+    /// match. Replaces the previous callback, for all the clones of this client.
+    ///
+    /// The callback runs on the receive task: it must not block.
+    pub fn on_unmatched<F>(&self, callback: F)
+    where
+        F: Fn(IpAddr, &IcmpPacket) + Send + Sync + 'static,
+    {
+        *self.reply_map.state.on_unmatched.lock() = Some(Box::new(callback));
+    }
+
+    /// Number of packets received that could not be decoded, see
+    /// [`on_malformed`](Self::on_malformed).
+    pub fn malformed_count(&self) -> u64 {
+        self.reply_map.state.malformed.load(Ordering::Relaxed)
+    }
+
+    /// Number of times the receive task went a read timeout without a datagram while
+    /// requests were pending, see `Config::builder().read_timeout()`.
+    pub fn recv_stall_count(&self) -> u64 {
+        self.reply_map.state.recv_stalls.load(Ordering::Relaxed)
+    }
+
+    /// Call `callback` with the sender address, the decoding error and the bytes of every
+    /// malformed packet received, as read from the socket, IP header included on raw IPv4
+    /// sockets. Replaces the previous callback, for all the clones of this client.
+    ///
+    /// The callback runs on the receive task: it must not block.
+    pub fn on_malformed<F>(&self, callback: F)
+    where
+        F: Fn(IpAddr, &MalformedPacketError, &[u8]) + Send + Sync + 'static,
+    {
+        *self.reply_map.state.on_malformed.lock() = Some(Box::new(callback));
+    }
+
+    /// The local address the socket is bound to, as assigned by the kernel.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// The receive buffer size granted by the kernel, to check the effect of
+    /// [`ConfigBuilder::recv_buffer_size`](crate::ConfigBuilder::recv_buffer_size).
+    pub fn recv_buffer_size(&self) -> io::Result<usize> {
+        self.socket.recv_buffer_size()
+    }
+
+    /// The send buffer size granted by the kernel, to check the effect of
+    /// [`ConfigBuilder::send_buffer_size`](crate::ConfigBuilder::send_buffer_size).
+    pub fn send_buffer_size(&self) -> io::Result<usize> {
+        self.socket.send_buffer_size()
+    }
+
+    /// Expose the underlying socket, if user wants to modify any options on it
+    pub fn get_socket(&self) -> AsyncSocket {
+        self.socket.clone()
+    }
+
+    /// Which kind of ICMP socket this client ended up with, e.g. to log it: it depends on
+    /// `Config::builder().sock_type_hint()` and on the privileges of the process.
+    pub fn socket_mode(&self) -> SocketMode {
+        if is_linux_icmp_socket!(self.socket.get_type()) {
+            SocketMode::DatagramIcmp
+        } else {
+            SocketMode::Raw
+        }
+    }
+}
+
+/// Periodically time out the waiters older than `ttl`, see `Config::waiter_ttl`.
+async fn reap_task(reply_map: ReplyMap, ttl: Duration) {
+    let mut interval = time::interval((ttl / 2).max(Duration::from_millis(1)));
+    interval.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        reply_map.reap(ttl);
+    }
+}
+
+/// Several sockets, e.g. with different TTLs or binds, served by a single receive task
+/// which feeds one reply map, instead of a task per [`Client`].
+///
+/// Replies are matched on `(host, ident, seq)` whichever socket they arrive on, so two
+/// pingers of the group must not use the same triple. On Linux ICMP sockets the identifier
+/// is set by the kernel and ignored: pingers of such members pinging the same host must use
+/// different sequence numbers.
+#[derive(Clone)]
+pub struct ClientGroup {
+    members: Vec<(AsyncSocket, PingOptions)>,
+    reply_map: ReplyMap,
+    recv: Arc<JoinHandle<()>>,
+    reaper: Option<Arc<JoinHandle<()>>>,
+}
+
+impl Drop for ClientGroup {
+    fn drop(&mut self) {
+        if Arc::strong_count(&self.recv) <= 1 {
+            self.recv.abort();
+            if let Some(reaper) = &self.reaper {
+                reaper.abort();
+            }
+            self.reply_map.clear();
+        }
+    }
+}
+
+impl ClientGroup {
+    /// A group with a socket for each configuration, in order. Waiters are reaped after
+    /// the shortest `waiter_ttl` of the configurations, if any sets one, and
+    /// `recv_batch_size` is ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`SurgeError::InsufficientPrivileges`] if the process may open no ICMP socket
+    /// of the kind of one of the configurations, or [`SurgeError::IOError`] if one of the
+    /// sockets cannot be created or one of the configured options is rejected by the system.
+    pub fn new(configs: &[Config]) -> Result<Self, SurgeError> {
+        let sockets = configs
+            .iter()
+            .map(|config| AsyncSocket::new(config).map_err(|err| socket_error(err, config.kind)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::with_sockets(sockets, configs))
+    }
+
+    /// A group of `sockets`, each configured by the configuration of the same index.
+    fn with_sockets(sockets: Vec<AsyncSocket>, configs: &[Config]) -> Self {
+        // The members share the reply map, one of them matching on sequence numbers alone
+        // makes all of them do so.
+        let match_policy = if configs
+            .iter()
+            .any(|config| config.match_policy == MatchPolicy::SeqOnly)
+        {
+            MatchPolicy::SeqOnly
+        } else {
+            MatchPolicy::IdentAndSeq
+        };
+        let shared_waiters = configs.iter().any(|config| config.allow_shared_waiters);
+        let reply_map = ReplyMap::new(match_policy, shared_waiters);
+        let recv = task::spawn(recv_group_task(
+            sockets
+                .iter()
+                .cloned()
+                .zip(configs.iter().map(RecvOptions::new))
+                .collect(),
+            reply_map.clone(),
+        ));
+        let reaper = configs
+            .iter()
+            .filter_map(|config| config.waiter_ttl)
+            .min()
+            .map(|ttl| Arc::new(task::spawn(reap_task(reply_map.clone(), ttl))));
+        Self {
+            members: sockets
+                .into_iter()
+                .zip(configs.iter().map(PingOptions::new))
+                .collect(),
+            reply_map,
+            recv: Arc::new(recv),
+            reaper,
+        }
+    }
+
+    /// Number of sockets in the group.
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    /// Create a `Pinger` sending through the socket of the `index`-th configuration,
+    /// see [`Client::pinger`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub async fn pinger(&self, index: usize, host: IpAddr, ident: PingIdentifier) -> Pinger {
+        let (socket, options) = &self.members[index];
+        Pinger::new(
+            host,
+            options.identifier(ident),
+            socket.clone(),
+            self.reply_map.clone(),
+            options.clone(),
+        )
+    }
+
+    /// Number of requests waiting for their reply, across all the sockets of the group.
+    pub fn pending_count(&self) -> usize {
+        self.reply_map.len()
+    }
+}
+
+/// The parts of [`Config`] the receive task needs.
+#[derive(Clone, Copy)]
+struct RecvOptions {
+    batch_size: usize,
+    verify_checksum: bool,
+    zero_based_sequence: bool,
+    read_timeout: Option<Duration>,
+}
+
+impl RecvOptions {
+    fn new(config: &Config) -> Self {
+        Self {
+            batch_size: config.recv_batch_size,
+            verify_checksum: config.verify_checksum,
+            zero_based_sequence: config.zero_based_sequence,
+            read_timeout: config.read_timeout,
+        }
+    }
+}
+
+/// Wait for `read`, reporting each `read_timeout` which goes by while requests are pending.
+async fn watched<T>(
+    read: impl Future<Output = T>,
+    reply_map: &ReplyMap,
+    read_timeout: Option<Duration>,
+) -> T {
+    let Some(timeout) = read_timeout else {
+        return read.await;
+    };
+    tokio::pin!(read);
+    loop {
+        match time::timeout(timeout, &mut read).await {
+            Ok(result) => return result,
+            Err(_) if !reply_map.is_empty() => reply_map.publish_stall(timeout),
+            Err(_) => {}
+        }
+    }
+}
+
+async fn recv_task(socket: AsyncSocket, reply_map: ReplyMap, options: RecvOptions) {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    if options.batch_size > 1 {
+        let mut batch = sys::RecvBatch::new(options.batch_size);
+        let mut errors = RecvErrors::default();
+        loop {
+            match watched(
+                socket.recv_batch(&mut batch),
+                &reply_map,
+                options.read_timeout,
+            )
+            .await
+            {
+                Ok(()) => {
+                    errors.reset();
+                    let timestamp = Instant::now();
+                    for (msg, message) in batch.iter() {
+                        dispatch_reply(&socket, &reply_map, options, msg, message, timestamp);
+                    }
+                }
+                Err(err) => {
+                    if errors.handle(&err).await.is_break() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    let mut buf = [0; 2048];
+    let mut errors = RecvErrors::default();
+    loop {
+        match watched(socket.recv_msg(&mut buf), &reply_map, options.read_timeout).await {
+            Ok(msg) => {
+                errors.reset();
+                let timestamp = Instant::now();
+                dispatch_reply(
+                    &socket,
+                    &reply_map,
+                    options,
+                    &msg,
+                    &buf[..msg.size],
+                    timestamp,
+                );
+            }
+            Err(err) => {
+                if errors.handle(&err).await.is_break() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// What a failed read of the socket means for the receive loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RecvFailure {
+    /// Interrupted by a signal, or nothing to read: the next read awaits readiness again.
+    Retry,
+    /// An error the kernel queued on the socket, such as an ICMP error for a datagram socket.
+    Transient,
+    /// An error of another kind, which may persist, e.g. `ENOBUFS` or `ENOMEM`.
+    Unexpected,
+    /// The socket is unusable, every later read would fail the same way.
+    Fatal,
+}
+
+/// `WouldBlock` is also what an expired `SO_RCVTIMEO` reports.
+fn classify_recv_error(err: &io::Error) -> RecvFailure {
+    match err.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted => RecvFailure::Retry,
+        io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionReset => RecvFailure::Transient,
+        _ if is_fatal_recv_error(err) => RecvFailure::Fatal,
+        _ => RecvFailure::Unexpected,
+    }
+}
+
+/// Whether `err` says the socket cannot be read at all: it was closed, is not a socket or
+/// is not in a state to be read.
+fn is_fatal_recv_error(err: &io::Error) -> bool {
+    #[cfg(unix)]
+    if matches!(err.raw_os_error(), Some(libc::EBADF | libc::ENOTSOCK)) {
+        return true;
+    }
+    matches!(
+        err.kind(),
+        io::ErrorKind::InvalidInput | io::ErrorKind::NotConnected
+    )
+}
+
+/// First wait of the receive loop after a failed read, doubled by each consecutive failure.
+const RECV_BACKOFF_MIN: Duration = Duration::from_millis(1);
+const RECV_BACKOFF_MAX: Duration = Duration::from_secs(1);
+/// At most one failed read is logged per interval, the others are counted in the next log.
+const RECV_ERROR_LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The failed reads of a receive loop since its last successful one, so that an error which
+/// persists neither spins the loop nor floods the log.
+#[derive(Debug, Default)]
+struct RecvErrors {
+    consecutive: u32,
+    last_logged: Option<Instant>,
+    /// Failed reads not logged since `last_logged`.
+    suppressed: u64,
+}
+
+impl RecvErrors {
+    fn reset(&mut self) {
+        self.consecutive = 0;
+    }
+
+    /// Report a failed read and tell the loop whether to go on, after backing off for
+    /// errors other than a retry.
+    async fn handle(&mut self, err: &io::Error) -> ControlFlow<()> {
+        let failure = classify_recv_error(err);
+        match failure {
+            RecvFailure::Retry => return ControlFlow::Continue(()),
+            RecvFailure::Fatal => {
+                #[cfg(feature = "tracing")]
+                tracing::error!(%err, "socket unusable, stopping the receive task");
+                #[cfg(not(feature = "tracing"))]
+                eprintln!("socket unusable, stopping the receive task: {err}");
+                return ControlFlow::Break(());
+            }
+            RecvFailure::Transient | RecvFailure::Unexpected => self.log(failure, err),
+        }
+        time::sleep(self.backoff()).await;
+        ControlFlow::Continue(())
+    }
+
+    /// The wait before the next read, counting this failure.
+    fn backoff(&mut self) -> Duration {
+        let backoff = RECV_BACKOFF_MIN
+            .checked_mul(1 << self.consecutive.min(16))
+            .map_or(RECV_BACKOFF_MAX, |backoff| backoff.min(RECV_BACKOFF_MAX));
+        self.consecutive = self.consecutive.saturating_add(1);
+        backoff
+    }
 
-            let ident = if is_linux_icmp_socket!(socket.get_type()) {
-                None
+    fn log(&mut self, failure: RecvFailure, err: &io::Error) {
+        let now = Instant::now();
+        if let Some(last) = self.last_logged {
+            if now.duration_since(last) < RECV_ERROR_LOG_INTERVAL {
+                self.suppressed += 1;
+                return;
+            }
+        }
+        self.last_logged = Some(now);
+        // Reads which failed since the last one logged.
+        let suppressed = mem::take(&mut self.suppressed);
+        #[cfg(feature = "tracing")]
+        if failure == RecvFailure::Transient {
+            tracing::debug!(%err, suppressed, "error receiving from the socket");
+        } else {
+            let kind = err.kind();
+            tracing::warn!(%err, ?kind, suppressed, "unexpected error receiving from the socket");
+        }
+        #[cfg(not(feature = "tracing"))]
+        {
+            let kind = err.kind();
+            let unexpected = if failure == RecvFailure::Transient {
+                ""
             } else {
-                Some(packet.get_identifier())
+                "unexpected "
             };
+            eprintln!(
+                "{unexpected}error receiving from the socket ({kind:?}): {err}, {suppressed} since last logged"
+            );
+        }
+    }
+}
 
-            if let Some(waiter) = reply_map.remove(addr.ip(), ident, packet.get_sequence()) {
-                // If send fails the receiving end has closed. Nothing to do.
-                let _ = waiter.send(Reply { timestamp, packet });
+/// Receive loop of a [`ClientGroup`], dispatching the datagrams of all its sockets.
+async fn recv_group_task(members: Vec<(AsyncSocket, RecvOptions)>, reply_map: ReplyMap) {
+    let reply_map = &reply_map;
+    // A socket which became unusable ends its own loop, not those of the others.
+    future::join_all(members.into_iter().map(|(socket, options)| async move {
+        let mut buf = [0; 2048];
+        let mut errors = RecvErrors::default();
+        loop {
+            match watched(socket.recv_msg(&mut buf), reply_map, options.read_timeout).await {
+                Ok(msg) => {
+                    errors.reset();
+                    let timestamp = Instant::now();
+                    dispatch_reply(
+                        &socket,
+                        reply_map,
+                        options,
+                        &msg,
+                        &buf[..msg.size],
+                        timestamp,
+                    );
+                }
+                Err(err) => {
+                    if errors.handle(&err).await.is_break() {
+                        return;
+                    }
+                }
             }
         }
+    }))
+    .await;
+}
+
+/// Whether the ICMP checksum of `message` is correct. ICMPv6 messages whose destination
+/// address is unknown cannot be checked and pass.
+fn checksum_ok(socket: &AsyncSocket, msg: &RecvMsg, message: &[u8], local_addr: IpAddr) -> bool {
+    match msg.addr.ip() {
+        IpAddr::V4(_) => icmpv4::checksum_ok(message, socket.sock_type),
+        IpAddr::V6(src_addr) => match msg.dst_addr.unwrap_or(local_addr) {
+            IpAddr::V6(dst_addr) if !dst_addr.is_unspecified() => {
+                icmpv6::checksum_ok(message, src_addr, dst_addr)
+            }
+            _ => true,
+        },
+    }
+}
+
+/// The `Instant` the kernel timestamped a datagram at, from its age on the system clock.
+/// `read`, when the datagram was read, if the system clock went backwards.
+fn received_at(kernel_time: SystemTime, read: Instant) -> Instant {
+    SystemTime::now()
+        .duration_since(kernel_time)
+        .ok()
+        .and_then(|age| Instant::now().checked_sub(age))
+        .map_or(read, |received| received.min(read))
+}
+
+/// Decode a received ICMP message and hand it over to whoever is waiting for it.
+fn dispatch_reply(
+    socket: &AsyncSocket,
+    reply_map: &ReplyMap,
+    options: RecvOptions,
+    msg: &RecvMsg,
+    message: &[u8],
+    timestamp: Instant,
+) {
+    let timestamp = msg
+        .kernel_time
+        .map_or(timestamp, |kernel_time| received_at(kernel_time, timestamp));
+    let local_addr = socket.local_addr().unwrap().ip();
+    let packet = {
+        let result = match msg.addr.ip() {
+            IpAddr::V4(src_addr) => {
+                let local_addr_ip4 = match local_addr {
+                    IpAddr::V4(local_addr_ip4) => local_addr_ip4,
+                    _ => return,
+                };
+
+                Icmpv4Packet::decode_with(
+                    message,
+                    socket.sock_type,
+                    src_addr,
+                    local_addr_ip4,
+                    options.zero_based_sequence,
+                )
+                .map(|mut packet| {
+                    if let (None, Some(ttl)) = (packet.get_ttl(), msg.hop_limit) {
+                        packet.ttl(ttl);
+                    }
+                    IcmpPacket::V4(packet)
+                })
+            }
+            IpAddr::V6(src_addr) => {
+                Icmpv6Packet::decode_with(message, src_addr, options.zero_based_sequence).map(
+                    |mut packet| {
+                        if let Some(hop_limit) = msg.hop_limit {
+                            packet.hop_limit(hop_limit);
+                        }
+                        IcmpPacket::V6(packet)
+                    },
+                )
+            }
+        };
+        match result {
+            Ok(packet) => packet,
+            // Expected traffic on raw sockets, not worth a log line each.
+            Err(SurgeError::EchoRequestPacket | SurgeError::NotPingRelated) => return,
+            Err(err) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(from = %msg.addr, %err, "dropping undecodable ICMP packet");
+                #[cfg(not(feature = "tracing"))]
+                eprintln!("error decoding ICMP packet: {err:?}");
+                if let SurgeError::MalformedPacket(err) = &err {
+                    reply_map.publish_malformed(msg.addr.ip(), err, message);
+                }
+                return;
+            }
+        }
+    };
+
+    let ident = if is_linux_icmp_socket!(socket.get_type()) {
+        None
+    } else {
+        Some(packet.get_identifier())
+    };
+
+    let seq = packet.get_sequence();
+    if options.verify_checksum && !checksum_ok(socket, msg, message, local_addr) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(from = %msg.addr, "ICMP packet with a bad checksum");
+        #[cfg(not(feature = "tracing"))]
+        eprintln!("bad checksum in ICMP packet from {}", msg.addr.ip());
+        if let Answered::Waiter(waiter) =
+            reply_map.take_answered(packet.get_real_dest(), ident, seq)
+        {
+            waiter.fail(|| SurgeError::BadChecksum);
+        }
+        return;
+    }
+
+    // ICMP error messages come from a router, the waiter is keyed on the host the
+    // echo request was originally sent to.
+    let host = packet.get_real_dest();
+    let mut reply = Reply { timestamp, packet };
+    if let Some(waiter) = reply_map.take_waiter(host, ident, seq) {
+        waiter.resolve(reply);
+        return;
+    }
+
+    // Collectors are keyed on the sequence number only, they must not take the replies
+    // other pingers of the client wait for.
+    if reply.packet.to_error().is_none() {
+        match reply_map.collect(ident, seq, reply) {
+            None => return,
+            Some(not_collected) => reply = not_collected,
+        }
+    }
+    match reply_map.unanswered(host, ident, seq) {
+        Answered::Unmatched => reply_map.publish_unmatched(msg.addr.ip(), &reply.packet),
+        Answered::Waiter(_) | Answered::Duplicate => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reply_map_shards() {
+        let reply_map = ReplyMap::default();
+        let seq = PingSequence(NonZeroU16::MIN);
+        let mut waiters = Vec::new();
+        for i in 0..10_000u32 {
+            let host = IpAddr::from(i.to_be_bytes());
+            waiters.push(reply_map.new_waiter(host, None, seq, None).unwrap());
+        }
+        assert!(matches!(
+            reply_map.new_waiter(IpAddr::from(42u32.to_be_bytes()), None, seq, None),
+            Err(SurgeError::IdenticalRequests { .. })
+        ));
+        for (i, waiter) in (0..10_000u32).zip(&waiters) {
+            let host = IpAddr::from(i.to_be_bytes());
+            assert!(reply_map
+                .remove(host, None, seq, waiter.generation)
+                .is_some());
+        }
+        assert!(reply_map
+            .shards
+            .iter()
+            .all(|shard| shard.lock().waiters.is_empty()));
+    }
+
+    #[test]
+    fn duplicate_replies() {
+        let reply_map = ReplyMap::default();
+        let host = IpAddr::from([127, 0, 0, 1]);
+        let seq = PingSequence(NonZeroU16::MIN);
+        let _waiter = reply_map.new_waiter(host, None, seq, None).unwrap();
+        assert!(matches!(
+            reply_map.take_answered(host, None, seq),
+            Answered::Waiter(_)
+        ));
+        assert!(matches!(
+            reply_map.take_answered(host, None, seq),
+            Answered::Duplicate
+        ));
+        assert!(matches!(
+            reply_map.take_answered(host, None, seq.next()),
+            Answered::Unmatched
+        ));
+        assert_eq!(reply_map.state.duplicate.load(Ordering::Relaxed), 1);
+        assert_eq!(reply_map.state.unmatched.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn shared_waiters() {
+        let reply_map = ReplyMap::new(MatchPolicy::IdentAndSeq, true);
+        let host = IpAddr::from([127, 0, 0, 1]);
+        let seq = PingSequence(NonZeroU16::MIN);
+        let first = reply_map.new_waiter(host, None, seq, None).unwrap();
+        let second = reply_map.new_waiter(host, None, seq, None).unwrap();
+        let third = reply_map.new_waiter(host, None, seq, None).unwrap();
+        // Only the waiters still there are answered.
+        drop(first);
+        assert_eq!(reply_map.len(), 1);
+        let Answered::Waiter(waiter) = reply_map.take_answered(host, None, seq) else {
+            panic!("no waiter");
+        };
+        waiter.resolve(Reply {
+            timestamp: Instant::now(),
+            packet: IcmpPacket::V4(Icmpv4Packet::default()),
+        });
+        assert!(second.await.is_ok());
+        assert!(third.await.is_ok());
+
+        // The request goes away with its last waiter.
+        let waiters: Vec<_> = (0..2)
+            .map(|_| reply_map.new_waiter(host, None, seq, None).unwrap())
+            .collect();
+        drop(waiters);
+        assert!(reply_map.is_empty());
+    }
+
+    #[test]
+    fn received_at() {
+        let read = Instant::now();
+        let age = Duration::from_millis(10);
+        let received = super::received_at(SystemTime::now() - age, read);
+        assert!(read - received > age / 2);
+        // The system clock went backwards.
+        assert_eq!(super::received_at(SystemTime::now() + age, read), read);
+    }
+
+    #[tokio::test]
+    async fn recv_errors() {
+        for (kind, failure) in [
+            (io::ErrorKind::Interrupted, RecvFailure::Retry),
+            (io::ErrorKind::WouldBlock, RecvFailure::Retry),
+            (io::ErrorKind::ConnectionRefused, RecvFailure::Transient),
+            (io::ErrorKind::Other, RecvFailure::Unexpected),
+            (io::ErrorKind::NotConnected, RecvFailure::Fatal),
+        ] {
+            assert_eq!(classify_recv_error(&kind.into()), failure);
+        }
+        #[cfg(unix)]
+        assert_eq!(
+            classify_recv_error(&io::Error::from_raw_os_error(libc::EBADF)),
+            RecvFailure::Fatal
+        );
+
+        // A persistent error backs off and is logged once.
+        let mut errors = RecvErrors::default();
+        let err = io::Error::from(io::ErrorKind::OutOfMemory);
+        for _ in 0..3 {
+            assert!(errors.handle(&err).await.is_continue());
+        }
+        assert_eq!(errors.suppressed, 2);
+        assert_eq!(errors.backoff(), RECV_BACKOFF_MIN * 8);
+        errors.consecutive = u32::MAX;
+        assert_eq!(errors.backoff(), RECV_BACKOFF_MAX);
+        errors.reset();
+        assert_eq!(errors.backoff(), RECV_BACKOFF_MIN);
+        assert!(errors
+            .handle(&io::ErrorKind::NotConnected.into())
+            .await
+            .is_break());
+    }
+
+    #[test]
+    fn match_policy() {
+        let host = IpAddr::from([127, 0, 0, 1]);
+        let seq = PingSequence(NonZeroU16::MIN);
+        for (policy, matched) in [
+            (MatchPolicy::IdentAndSeq, false),
+            (MatchPolicy::SeqOnly, true),
+        ] {
+            let reply_map = ReplyMap::new(policy, false);
+            let _waiter = reply_map
+                .new_waiter(host, Some(PingIdentifier(1)), seq, None)
+                .unwrap();
+            assert_eq!(
+                matches!(
+                    reply_map.take_answered(host, Some(PingIdentifier(2)), seq),
+                    Answered::Waiter(_)
+                ),
+                matched
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn with_identifier() {
+        // Needs the permission to open a raw ICMP socket.
+        let config = Config::builder()
+            .sock_type_hint(SockType::RAW)
+            .match_policy(MatchPolicy::SeqOnly)
+            .build();
+        let ident = PingIdentifier(0x5150);
+        let Ok(client) = Client::with_identifier(&config, ident) else {
+            return;
+        };
+        if client.socket.get_type() != SockType::RAW {
+            return;
+        }
+        assert_eq!(client.socket_mode(), SocketMode::Raw);
+        let host = IpAddr::from([127, 0, 0, 1]);
+        let pinger = client.pinger(host, PingIdentifier(1)).await;
+        assert_eq!(pinger.ident, Some(ident));
+
+        let seq = PingSequence(NonZeroU16::MIN);
+        let _waiter = pinger.ping_send(seq, &[]).await.unwrap();
+        assert!(matches!(
+            client
+                .reply_map
+                .take_answered(host, Some(PingIdentifier(1)), seq),
+            Answered::Unmatched
+        ));
+    }
+
+    #[tokio::test]
+    async fn address_family_mismatch() {
+        let seq = PingSequence(NonZeroU16::MIN);
+        for (local, host) in [
+            ("127.0.0.1:0", IpAddr::from(Ipv6Addr::LOCALHOST)),
+            ("[::1]:0", IpAddr::from(Ipv4Addr::LOCALHOST)),
+        ] {
+            // IPv6 may be disabled.
+            let Ok(socket) = UdpSocket::bind(local).await else {
+                continue;
+            };
+            let client = Client::from_test_transport(socket);
+            assert!(matches!(
+                client.try_pinger(host, PingIdentifier(1)).await,
+                Err(SurgeError::AddressFamilyMismatch { host: h, .. }) if h == host
+            ));
+            let pinger = client.pinger(host, PingIdentifier(1)).await;
+            assert!(matches!(
+                pinger.ping(seq, &[]).await,
+                Err(SurgeError::AddressFamilyMismatch { .. })
+            ));
+            assert_eq!(client.pending_count(), 0);
+        }
+    }
+
+    #[tokio::test]
+    async fn flow_label_too_large() {
+        let config = Config::builder()
+            .kind(ICMP::V6)
+            .flow_label(MAX_FLOW_LABEL + 1)
+            .build();
+        assert!(matches!(
+            Client::new(&config),
+            Err(SurgeError::IOError(err)) if err.kind() == io::ErrorKind::InvalidInput
+        ));
+    }
+
+    // Other platforms expect the IP header in front of the ICMP message.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[tokio::test]
+    async fn malformed_packets() {
+        let (peer, client) = Client::test_transport_pair().await;
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        client.on_malformed(move |_, err, bytes| {
+            let _ = tx.send((
+                matches!(err, MalformedPacketError::NotIcmpv4Packet),
+                bytes.to_vec(),
+            ));
+        });
+        // Too short for an ICMP header.
+        peer.send(&[0, 0, 0]).await.unwrap();
+        assert_eq!(rx.recv().await.unwrap(), (true, vec![0, 0, 0]));
+        assert_eq!(client.malformed_count(), 1);
+    }
+
+    // Other platforms expect the IP header in front of the ICMP message.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[tokio::test]
+    async fn test_transport() {
+        let client = Client::test_echo(|_| true).await;
+        assert_eq!(client.socket_mode(), SocketMode::DatagramIcmp);
+        let pinger = client
+            .pinger(IpAddr::from([127, 0, 0, 1]), PingIdentifier(1))
+            .await;
+        assert_eq!(pinger.ident, None);
+        for seq in 1..=3 {
+            let (packet, _) = pinger
+                .ping(PingSequence(NonZeroU16::new(seq).unwrap()), b"payload")
+                .await
+                .unwrap();
+            assert_eq!(packet.get_sequence().into_u16(), seq);
+            assert_eq!(packet.get_payload(), b"payload");
+        }
+        let before = Instant::now();
+        let (_, rtt, received) = pinger
+            .ping_full(PingSequence(NonZeroU16::new(4).unwrap()), b"payload")
+            .await
+            .unwrap();
+        assert!(received >= before && received <= Instant::now());
+        assert!(received.duration_since(before) >= rtt);
+        assert_eq!(client.pending_count(), 0);
+    }
+
+    // Other platforms expect the IP header in front of the ICMP message.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[tokio::test]
+    async fn ping_list() {
+        // Drop the first request of every host.
+        let client = Client::test_echo(|request| request[7] != 1).await;
+        let host = IpAddr::from([127, 0, 0, 1]);
+        for (retries, answered) in [(0, false), (1, true)] {
+            let opts = PingListOpts {
+                timeout: Duration::from_millis(100),
+                retries,
+                ..PingListOpts::default()
+            };
+            let results: Vec<_> = client.ping_list([host], opts).collect().await;
+            assert_eq!(results.len(), 1);
+            assert_eq!(results[0].0, host);
+            assert_eq!(results[0].1.is_ok(), answered);
+        }
+        assert_eq!(client.pending_count(), 0);
+    }
+
+    // Other platforms expect the IP header in front of the ICMP message.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[tokio::test]
+    async fn blast() {
+        let (peer, client) = Client::test_transport_pair().await;
+        let report = client
+            .blast(IpAddr::from([127, 0, 0, 1]), 5, 16, 1000)
+            .await
+            .unwrap();
+        assert_eq!(report.sent, 5);
+        assert!(report.errors.is_empty());
+        assert_eq!(client.pending_count(), 0);
+
+        let mut buf = [0; 2048];
+        for seq in 1..=5 {
+            assert_eq!(peer.recv(&mut buf).await.unwrap(), 8 + 16);
+            assert_eq!(u16::from_be_bytes([buf[6], buf[7]]), seq);
+        }
+    }
+
+    #[tokio::test]
+    async fn shutdown() {
+        let client = Client::test_echo(|_| false).await;
+        let clone = client.clone();
+        let pinger = clone
+            .pinger(IpAddr::from([127, 0, 0, 1]), PingIdentifier(1))
+            .await;
+        let seq = PingSequence(NonZeroU16::MIN);
+        let (send_time, waiter) = pinger.ping_send(seq, b"payload").await.unwrap();
+        client.shutdown(Duration::from_millis(50)).await;
+
+        assert!(matches!(
+            pinger.ping_recv(send_time, waiter).await,
+            Err(SurgeError::ClientShutdown)
+        ));
+        assert!(matches!(
+            pinger.ping(seq.next(), b"payload").await,
+            Err(SurgeError::ClientShutdown)
+        ));
+        assert!(clone.recv.lock().is_none());
+    }
+
+    // Other platforms expect the IP header in front of the ICMP message.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[tokio::test]
+    async fn client_group() {
+        let sockets = vec![
+            AsyncSocket::test_echo(|_| true).await,
+            AsyncSocket::test_echo(|_| true).await,
+        ];
+        let group = ClientGroup::with_sockets(sockets, &[Config::default(), Config::default()]);
+
+        // The replies of both sockets are dispatched to the pingers of the group.
+        let host = IpAddr::from([127, 0, 0, 1]);
+        let first = group.pinger(0, host, PingIdentifier(1)).await;
+        let second = group.pinger(1, host, PingIdentifier(2)).await;
+        let seq = PingSequence(NonZeroU16::MIN);
+        let (first, second) = tokio::join!(
+            first.ping_timeout(seq, b"first", Duration::from_secs(1)),
+            second.ping_timeout(seq.next(), b"second", Duration::from_secs(1)),
+        );
+        assert_eq!(first.unwrap().0.get_payload(), b"first");
+        assert_eq!(second.unwrap().0.get_payload(), b"second");
+        assert_eq!(group.pending_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn read_timeout() {
+        let config = Config::builder()
+            .read_timeout(Duration::from_millis(20))
+            .build();
+        let client = Client::with_socket(AsyncSocket::test_echo(|_| false).await, &config);
+        // An idle socket is no stall.
+        time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(client.recv_stall_count(), 0);
+
+        let pinger = client
+            .pinger(IpAddr::from([127, 0, 0, 1]), PingIdentifier(1))
+            .await;
+        let seq = PingSequence(NonZeroU16::MIN);
+        let _waiter = pinger.ping_send(seq, b"payload").await.unwrap();
+        time::sleep(Duration::from_millis(100)).await;
+        assert!(client.recv_stall_count() >= 2);
+    }
+
+    // Only Linux queries the MTU of an interface.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[tokio::test]
+    async fn enforce_mtu() {
+        let (_peer, socket) = AsyncSocket::test_transport_pair().await;
+        let config = Config::builder().interface("lo").enforce_mtu(true).build();
+        let mtu_check = AsyncSocket::mtu_check(&SockRef::from(&*socket.inner), &config).unwrap();
+        let socket = AsyncSocket {
+            mtu_check: Some(mtu_check),
+            ..socket
+        };
+        let client = Client::with_socket(socket, &config);
+        let pinger = client
+            .pinger(IpAddr::from([127, 0, 0, 1]), PingIdentifier(1))
+            .await;
+        let seq = PingSequence(NonZeroU16::MIN);
+        assert!(matches!(
+            pinger.send_ping(seq, &[0; 70_000]).await,
+            Err(SurgeError::ExceedsMtu { size: 70_028, .. })
+        ));
+        assert!(pinger.send_ping(seq, b"payload").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn sweep_cidr_rejects_large_networks() {
+        let client = Client::test_echo(|_| false).await;
+
+        for cidr in ["10.0.0.0/8", "2001:db8::/64"] {
+            let cidr = cidr.parse().unwrap();
+            assert!(matches!(
+                client.sweep_cidr(cidr, b"", Duration::from_millis(1), 1).await,
+                Err(SurgeError::NetworkTooLarge { max, .. }) if max == Client::SWEEP_MAX_HOSTS
+            ));
+        }
+        // Smaller networks are swept.
+        let cidr = "127.0.0.0/30".parse().unwrap();
+        let results = client
+            .sweep_cidr(cidr, b"", Duration::from_millis(1), 2)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 2);
     }
 }
@@ -1,8 +1,58 @@
-use std::net::SocketAddr;
+use std::{net::SocketAddr, time::Duration};
 
 use socket2::{SockAddr, Type};
 
-use crate::ICMP;
+use crate::{PingIdentifier, ICMP};
+
+/// How `Client::pinger` picks the ICMP identifier of a pinger, see
+/// [`ConfigBuilder::identifier_strategy`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdentifierStrategy {
+    /// Every pinger uses this identifier, e.g. to match an external capture.
+    Fixed(PingIdentifier),
+    /// Every pinger gets a random identifier, to reduce cross-talk between pingers.
+    Random,
+}
+
+/// Which fields of a reply must match the request it answers, besides the host.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MatchPolicy {
+    /// Identifier and sequence number.
+    #[default]
+    IdentAndSeq,
+    /// The sequence number alone, e.g. to replay a capture taken with another identifier.
+    /// Pingers of the same host must then use distinct sequence numbers.
+    SeqOnly,
+}
+
+/// Who computes the checksum of ICMPv6 echo requests, see [`ConfigBuilder::icmpv6_checksum`].
+///
+/// The checksum covers a pseudo-header with the source address, which the kernel knows
+/// better: per RFC 3542 it fills it in on ICMPv6 sockets, and some kernels reject a
+/// request whose checksum was already set. Others leave it to the caller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Icmpv6Checksum {
+    /// Leave the checksum zero, the kernel fills it in.
+    Kernel,
+    /// Compute the checksum, using the source address the request is sent from.
+    Manual,
+}
+
+impl Default for Icmpv6Checksum {
+    fn default() -> Self {
+        if cfg!(any(
+            target_os = "android",
+            target_os = "freebsd",
+            target_os = "ios",
+            target_os = "linux",
+            target_os = "macos",
+        )) {
+            Icmpv6Checksum::Kernel
+        } else {
+            Icmpv6Checksum::Manual
+        }
+    }
+}
 
 /// Config is the packaging of various configurations of `sockets`. If you want to make
 /// some `set_socket_opt` and other modifications, please define and implement them in `Config`.
@@ -13,6 +63,40 @@ pub struct Config {
     pub bind: Option<SockAddr>,
     pub interface: Option<String>,
     pub ttl: Option<u32>,
+    pub hop_limit: Option<u32>,
+    pub tos: Option<u8>,
+    pub dont_fragment: Option<bool>,
+    pub verify_payload: bool,
+    pub recv_batch_size: usize,
+    pub allow_flood: bool,
+    pub fwmark: Option<u32>,
+    pub recv_buffer_size: Option<usize>,
+    pub send_buffer_size: Option<usize>,
+    pub max_pps: Option<u32>,
+    pub waiter_ttl: Option<Duration>,
+    pub broadcast: bool,
+    pub multicast_hops: Option<u32>,
+    pub multicast_interface: Option<u32>,
+    pub identifier_strategy: Option<IdentifierStrategy>,
+    pub embed_timestamp: bool,
+    pub verify_checksum: bool,
+    pub read_timeout: Option<Duration>,
+    pub icmp_type_filter: Option<Vec<u8>>,
+    pub manual_recv: bool,
+    pub reuse_address: bool,
+    pub reuse_port: bool,
+    pub priority: Option<u32>,
+    pub record_route: bool,
+    pub match_policy: MatchPolicy,
+    pub enforce_mtu: bool,
+    pub hardware_timestamps: bool,
+    pub bind_port: Option<u16>,
+    pub zero_based_sequence: bool,
+    pub icmpv6_checksum: Icmpv6Checksum,
+    pub send_pool: bool,
+    pub adaptive: bool,
+    pub flow_label: Option<u32>,
+    pub allow_shared_waiters: bool,
     pub fib: Option<u32>,
 }
 
@@ -24,6 +108,40 @@ impl Default for Config {
             bind: None,
             interface: None,
             ttl: None,
+            hop_limit: None,
+            tos: None,
+            dont_fragment: None,
+            verify_payload: false,
+            recv_batch_size: 1,
+            allow_flood: false,
+            fwmark: None,
+            recv_buffer_size: None,
+            send_buffer_size: None,
+            max_pps: None,
+            waiter_ttl: None,
+            broadcast: false,
+            multicast_hops: None,
+            multicast_interface: None,
+            identifier_strategy: None,
+            embed_timestamp: false,
+            verify_checksum: false,
+            read_timeout: None,
+            icmp_type_filter: None,
+            manual_recv: false,
+            reuse_address: false,
+            reuse_port: false,
+            priority: None,
+            record_route: false,
+            match_policy: MatchPolicy::IdentAndSeq,
+            enforce_mtu: false,
+            hardware_timestamps: false,
+            bind_port: None,
+            zero_based_sequence: false,
+            icmpv6_checksum: Icmpv6Checksum::default(),
+            send_pool: false,
+            adaptive: false,
+            flow_label: None,
+            allow_shared_waiters: false,
             fib: None,
         }
     }
@@ -47,6 +165,40 @@ pub struct ConfigBuilder {
     bind: Option<SockAddr>,
     interface: Option<String>,
     ttl: Option<u32>,
+    hop_limit: Option<u32>,
+    tos: Option<u8>,
+    dont_fragment: Option<bool>,
+    verify_payload: bool,
+    recv_batch_size: usize,
+    allow_flood: bool,
+    fwmark: Option<u32>,
+    recv_buffer_size: Option<usize>,
+    send_buffer_size: Option<usize>,
+    max_pps: Option<u32>,
+    waiter_ttl: Option<Duration>,
+    broadcast: bool,
+    multicast_hops: Option<u32>,
+    multicast_interface: Option<u32>,
+    identifier_strategy: Option<IdentifierStrategy>,
+    embed_timestamp: bool,
+    verify_checksum: bool,
+    read_timeout: Option<Duration>,
+    icmp_type_filter: Option<Vec<u8>>,
+    manual_recv: bool,
+    reuse_address: bool,
+    reuse_port: bool,
+    priority: Option<u32>,
+    record_route: bool,
+    match_policy: MatchPolicy,
+    enforce_mtu: bool,
+    hardware_timestamps: bool,
+    bind_port: Option<u16>,
+    zero_based_sequence: bool,
+    icmpv6_checksum: Icmpv6Checksum,
+    send_pool: bool,
+    adaptive: bool,
+    flow_label: Option<u32>,
+    allow_shared_waiters: bool,
     fib: Option<u32>,
 }
 
@@ -58,6 +210,40 @@ impl Default for ConfigBuilder {
             bind: None,
             interface: None,
             ttl: None,
+            hop_limit: None,
+            tos: None,
+            dont_fragment: None,
+            verify_payload: false,
+            recv_batch_size: 1,
+            allow_flood: false,
+            fwmark: None,
+            recv_buffer_size: None,
+            send_buffer_size: None,
+            max_pps: None,
+            waiter_ttl: None,
+            broadcast: false,
+            multicast_hops: None,
+            multicast_interface: None,
+            identifier_strategy: None,
+            embed_timestamp: false,
+            verify_checksum: false,
+            read_timeout: None,
+            icmp_type_filter: None,
+            manual_recv: false,
+            reuse_address: false,
+            reuse_port: false,
+            priority: None,
+            record_route: false,
+            match_policy: MatchPolicy::IdentAndSeq,
+            enforce_mtu: false,
+            hardware_timestamps: false,
+            bind_port: None,
+            zero_based_sequence: false,
+            icmpv6_checksum: Icmpv6Checksum::default(),
+            send_pool: false,
+            adaptive: false,
+            flow_label: None,
+            allow_shared_waiters: false,
             fib: None,
         }
     }
@@ -78,6 +264,9 @@ impl ConfigBuilder {
     /// If a socket is bound to an interface, only packets received from that
     /// particular interface are processed by the socket. Note that this only
     /// works for some socket types, particularly `AF_INET` sockets.
+    ///
+    /// It can be combined with [`bind`](Self::bind) to also pin the source address.
+    /// Only available on Linux, Android and Fuchsia: `Client::new` fails elsewhere.
     pub fn interface(mut self, interface: &str) -> Self {
         self.interface = Some(interface.to_string());
         self
@@ -86,12 +275,327 @@ impl ConfigBuilder {
     /// Set the value of the `IP_TTL` option for this socket.
     ///
     /// This value sets the time-to-live field that is used in every packet sent
-    /// from this socket.
+    /// from this socket. Only applies to `ICMP::V4`, see [`hop_limit`](Self::hop_limit) for IPv6.
     pub fn ttl(mut self, ttl: u32) -> Self {
         self.ttl = Some(ttl);
         self
     }
 
+    /// Set the value of the `IPV6_UNICAST_HOPS` option for this socket.
+    ///
+    /// This value sets the hop limit field that is used in every packet sent
+    /// from this socket. Only applies to `ICMP::V6`.
+    pub fn hop_limit(mut self, hop_limit: u32) -> Self {
+        self.hop_limit = Some(hop_limit);
+        self
+    }
+
+    /// Set the `IP_TOS` (IPv4) or `IPV6_TCLASS` (IPv6) byte of every packet sent from this socket.
+    ///
+    /// The value is passed through unchanged: DSCP in the upper 6 bits, ECN in the lower 2.
+    /// Some values (e.g. precedence above `0xa0` on Linux) require `CAP_NET_ADMIN`.
+    pub fn tos(mut self, tos: u8) -> Self {
+        self.tos = Some(tos);
+        self
+    }
+
+    /// Set the 20-bit flow label of every IPv6 packet sent from this socket, e.g. to pick
+    /// which of several equal-cost paths probes take. Only applies to `ICMP::V6`, Linux only.
+    ///
+    /// The label is leased from the kernel (`IPV6_FLOWLABEL_MGR`) and sent with
+    /// `IPV6_FLOWINFO_SEND`. Creating the client fails with an `InvalidInput` io error if
+    /// the value does not fit in 20 bits.
+    pub fn flow_label(mut self, flow_label: u32) -> Self {
+        self.flow_label = Some(flow_label);
+        self
+    }
+
+    /// Set or clear the Don't Fragment bit of every packet sent from this socket
+    /// (`IP_MTU_DISCOVER` / `IPV6_MTU_DISCOVER`), for path MTU probing. Linux only.
+    ///
+    /// Oversized requests then either fail to send with an `EMSGSIZE` io error or, on raw
+    /// sockets, are answered by a router with [`SurgeError::FragmentationNeeded`](crate::SurgeError::FragmentationNeeded).
+    pub fn dont_fragment(mut self, dont_fragment: bool) -> Self {
+        self.dont_fragment = Some(dont_fragment);
+        self
+    }
+
+    /// Check that every echo reply carries back the exact payload of its request,
+    /// failing with `SurgeError::PayloadMismatch` otherwise. (default: false)
+    pub fn verify_payload(mut self, verify_payload: bool) -> Self {
+        self.verify_payload = verify_payload;
+        self
+    }
+
+    /// Number of datagrams the receive task reads per `recvmmsg(2)` call. (default: 1)
+    ///
+    /// Larger batches save syscalls at high reply rates. Linux only, ignored elsewhere.
+    pub fn recv_batch_size(mut self, recv_batch_size: usize) -> Self {
+        self.recv_batch_size = recv_batch_size;
+        self
+    }
+
+    /// Acknowledge that [`Pinger::flood`](crate::Pinger::flood) may be used with this client.
+    ///
+    /// Flooding sends requests as fast as replies come back, which loads the target and the
+    /// network heavily and usually needs elevated privileges. (default: false)
+    pub fn allow_flood(mut self, allow_flood: bool) -> Self {
+        self.allow_flood = allow_flood;
+        self
+    }
+
+    /// Set the `SO_MARK` (fwmark) of every packet sent from this socket, for policy routing.
+    ///
+    /// Requires `CAP_NET_ADMIN`. Only available on Linux, Android and Fuchsia: `Client::new`
+    /// fails elsewhere.
+    pub fn fwmark(mut self, fwmark: u32) -> Self {
+        self.fwmark = Some(fwmark);
+        self
+    }
+
+    /// Set the `SO_RCVBUF` size of the socket, to absorb bursts of replies at high rates.
+    ///
+    /// The kernel may grant a different size (Linux doubles it), see
+    /// [`Client::recv_buffer_size`](crate::Client::recv_buffer_size).
+    pub fn recv_buffer_size(mut self, recv_buffer_size: usize) -> Self {
+        self.recv_buffer_size = Some(recv_buffer_size);
+        self
+    }
+
+    /// Set the `SO_SNDBUF` size of the socket.
+    ///
+    /// The kernel may grant a different size (Linux doubles it), see
+    /// [`Client::send_buffer_size`](crate::Client::send_buffer_size).
+    pub fn send_buffer_size(mut self, send_buffer_size: usize) -> Self {
+        self.send_buffer_size = Some(send_buffer_size);
+        self
+    }
+
+    /// Cap the number of packets per second sent by all the pingers of the client.
+    ///
+    /// Once the budget is used up, sending waits for the next slot instead of failing.
+    /// A value of 0 is treated as no limit.
+    pub fn max_pps(mut self, max_pps: u32) -> Self {
+        self.max_pps = Some(max_pps);
+        self
+    }
+
+    /// Resolve requests still waiting for a reply after `waiter_ttl` with
+    /// `SurgeError::Timeout`, as a safety net for callers that never time them out.
+    ///
+    /// A background task checks the pending requests every `waiter_ttl / 2`. (default: none)
+    pub fn waiter_ttl(mut self, waiter_ttl: Duration) -> Self {
+        self.waiter_ttl = Some(waiter_ttl);
+        self
+    }
+
+    /// Set `SO_BROADCAST`, required to ping broadcast addresses, see
+    /// [`Pinger::ping_broadcast`](crate::Pinger::ping_broadcast). (default: false)
+    pub fn broadcast(mut self, broadcast: bool) -> Self {
+        self.broadcast = broadcast;
+        self
+    }
+
+    /// Set the `IP_MULTICAST_TTL` (IPv4) or `IPV6_MULTICAST_HOPS` (IPv6) option, the TTL or
+    /// hop limit of requests sent to multicast addresses. The system default is 1, which
+    /// keeps them on the local link.
+    pub fn multicast_hops(mut self, multicast_hops: u32) -> Self {
+        self.multicast_hops = Some(multicast_hops);
+        self
+    }
+
+    /// Set the `IPV6_MULTICAST_IF` option, the index of the interface requests to multicast
+    /// addresses are sent from. Only applies to `ICMP::V6`; the scope id of the destination,
+    /// see [`Pinger::scope_id`](crate::Pinger::scope_id), serves the same purpose.
+    pub fn multicast_interface(mut self, multicast_interface: u32) -> Self {
+        self.multicast_interface = Some(multicast_interface);
+        self
+    }
+
+    /// Choose the ICMP identifier of the pingers created by `Client::pinger`, instead of the
+    /// `ident` passed to it.
+    ///
+    /// Ignored on Linux ICMP sockets, where the kernel sets the identifier itself and
+    /// [`Pinger::ident`](crate::Pinger::ident) stays `None`.
+    pub fn identifier_strategy(mut self, identifier_strategy: IdentifierStrategy) -> Self {
+        self.identifier_strategy = Some(identifier_strategy);
+        self
+    }
+
+    /// Write the send time into the first 8 bytes of the payload of every echo request, as
+    /// `ping` does, and compute the round trip time from the copy echoed back. Payloads
+    /// shorter than 8 bytes are rejected with `SurgeError::PayloadTooShortForTimestamp`.
+    /// (default: false)
+    pub fn embed_timestamp(mut self, embed_timestamp: bool) -> Self {
+        self.embed_timestamp = embed_timestamp;
+        self
+    }
+
+    /// Recompute the ICMP checksum of every received packet. A request whose reply has a
+    /// bad checksum fails with `SurgeError::BadChecksum`; corrupted packets which cannot be
+    /// matched to a request are dropped. (default: false)
+    ///
+    /// The ICMPv6 checksum covers the destination address: on Linux it is obtained with
+    /// `IPV6_RECVPKTINFO`, elsewhere it is only known if the socket is bound to an address,
+    /// see [`bind`](Self::bind), and packets are not checked otherwise.
+    pub fn verify_checksum(mut self, verify_checksum: bool) -> Self {
+        self.verify_checksum = verify_checksum;
+        self
+    }
+
+    /// Watch the receive task: each time `timeout` goes by without a datagram while requests
+    /// are waiting for their reply, it logs a warning and counts the stall, see
+    /// [`Client::recv_stall_count`](crate::Client::recv_stall_count). Also set as
+    /// `SO_RCVTIMEO` on the socket, for blocking reads of it, e.g. through
+    /// [`AsyncSocket::get_native_sock`](crate::AsyncSocket::get_native_sock).
+    ///
+    /// The watchdog only reports: how long a ping waits for its reply is bounded by its own
+    /// timeout, e.g. [`Pinger::ping_timeout`](crate::Pinger::ping_timeout), or by
+    /// [`waiter_ttl`](Self::waiter_ttl). With a read timeout shorter than the ping timeout,
+    /// a stall is reported before the pings waiting on it time out.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Have the kernel deliver only the ICMP messages of these types, e.g. echo reply,
+    /// time exceeded and destination unreachable, sparing the receive task the unrelated
+    /// ICMP traffic of a busy host.
+    ///
+    /// Linux only, with `ICMP_FILTER` / `ICMP6_FILTER`, which only apply to raw sockets:
+    /// creating the client fails with an `Unsupported` I/O error otherwise. Linux ICMP
+    /// sockets need no filter, they only receive the messages related to their requests.
+    /// IPv4 types above 31 cannot be filtered and are always delivered.
+    pub fn icmp_type_filter(mut self, types: &[u8]) -> Self {
+        self.icmp_type_filter = Some(types.to_vec());
+        self
+    }
+
+    /// Do not spawn the task receiving the replies: the application drives the receptions
+    /// itself by calling [`Client::recv_once`](crate::Client::recv_once) in its own loop,
+    /// e.g. on a single-threaded or custom runtime. (default: false)
+    ///
+    /// Ignored by [`ClientGroup`](crate::ClientGroup).
+    pub fn manual_recv(mut self, manual_recv: bool) -> Self {
+        self.manual_recv = manual_recv;
+        self
+    }
+
+    /// Set `SO_REUSEADDR`, so that another socket can bind the same address, e.g. for an
+    /// active/standby pair of monitors. (default: false)
+    pub fn reuse_address(mut self, reuse_address: bool) -> Self {
+        self.reuse_address = reuse_address;
+        self
+    }
+
+    /// Set `SO_REUSEPORT`, Unix only except Solaris and illumos. (default: false)
+    pub fn reuse_port(mut self, reuse_port: bool) -> Self {
+        self.reuse_port = reuse_port;
+        self
+    }
+
+    /// Set `SO_PRIORITY`, the queueing priority of the outgoing packets, e.g. to tag probe
+    /// traffic for traffic control. Linux only.
+    pub fn priority(mut self, priority: u32) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    /// Ask the routers on the way to record their address in the IPv4 Record Route option
+    /// of the requests, see [`Icmpv4Packet::get_recorded_route`]. Needs a raw IPv4 socket,
+    /// Linux only. (default: false)
+    ///
+    /// [`Icmpv4Packet::get_recorded_route`]: crate::Icmpv4Packet::get_recorded_route
+    pub fn record_route(mut self, record_route: bool) -> Self {
+        self.record_route = record_route;
+        self
+    }
+
+    /// How replies are matched to requests. (default: [`MatchPolicy::IdentAndSeq`])
+    pub fn match_policy(mut self, match_policy: MatchPolicy) -> Self {
+        self.match_policy = match_policy;
+        self
+    }
+
+    /// Let several waiters be registered for the same request, e.g. with `Pinger::ping_send`
+    /// from two subsystems, instead of failing with [`SurgeError::IdenticalRequests`]. Each
+    /// of them gets a copy of the reply; cancelling, resetting or dropping the pinger of the
+    /// request fails them all. (default: false)
+    ///
+    /// [`SurgeError::IdenticalRequests`]: crate::SurgeError::IdenticalRequests
+    pub fn allow_shared_waiters(mut self, allow_shared_waiters: bool) -> Self {
+        self.allow_shared_waiters = allow_shared_waiters;
+        self
+    }
+
+    /// Refuse to send echo requests which do not fit in the MTU of the interface the socket
+    /// is bound to, by [`interface`](Self::interface) or by the [`bind`](Self::bind) address,
+    /// rather than have them fragmented or dropped. Linux only. (default: false)
+    pub fn enforce_mtu(mut self, enforce_mtu: bool) -> Self {
+        self.enforce_mtu = enforce_mtu;
+        self
+    }
+
+    /// Take the receive time of the replies from the kernel rather than from the receive
+    /// task, which adds its scheduling delay to the round trip times. `SO_TIMESTAMPING` is
+    /// tried first, for the timestamps of NICs which have receive timestamping enabled and
+    /// their clock synchronized to the system clock (e.g. by `phc2sys`), then
+    /// `SO_TIMESTAMPNS`; if neither is available the software timestamps are kept.
+    /// Linux only, ignored elsewhere. (default: false)
+    pub fn hardware_timestamps(mut self, hardware_timestamps: bool) -> Self {
+        self.hardware_timestamps = hardware_timestamps;
+        self
+    }
+
+    /// Bind the socket to this port, of the [`bind`](Self::bind) address or of the
+    /// unspecified address.
+    ///
+    /// Linux ICMP sockets use their port as the identifier of the requests, in place of the
+    /// `ident` of the pingers, which is why [`Pinger::ident`](crate::Pinger::ident) is
+    /// `None` for them: the port pins the identifier on the wire to a known value, e.g. for
+    /// firewall rules or to reproduce a capture. Two sockets cannot be bound to the same
+    /// port without [`reuse_port`](Self::reuse_port). Raw sockets have no port, the option
+    /// makes no difference to them.
+    pub fn bind_port(mut self, bind_port: u16) -> Self {
+        self.bind_port = Some(bind_port);
+        self
+    }
+
+    /// Put sequence numbers on the wire one less than the [`PingSequence`]s of the requests,
+    /// so that `PingSequence(1)` sends and matches sequence number 0, e.g. for conformance
+    /// tests. Sequence number 65535 cannot be sent then. Replies and error messages report
+    /// the `PingSequence` of the request as well. (default: false)
+    ///
+    /// [`PingSequence`]: crate::PingSequence
+    pub fn zero_based_sequence(mut self, zero_based_sequence: bool) -> Self {
+        self.zero_based_sequence = zero_based_sequence;
+        self
+    }
+
+    /// Who computes the checksum of ICMPv6 echo requests. (default: [`Icmpv6Checksum::Kernel`]
+    /// where the kernel is known to fill it in, [`Icmpv6Checksum::Manual`] elsewhere)
+    pub fn icmpv6_checksum(mut self, icmpv6_checksum: Icmpv6Checksum) -> Self {
+        self.icmpv6_checksum = icmpv6_checksum;
+        self
+    }
+
+    /// Build the packets sent in buffers reused across the sends of the client, instead of
+    /// allocating one per packet, to spare the allocator at high rates. (default: false)
+    pub fn send_pool(mut self, send_pool: bool) -> Self {
+        self.send_pool = send_pool;
+        self
+    }
+
+    /// Send the next request of a [`PingSession`](crate::PingSession) or
+    /// [`PingStream`](crate::PingStream) as soon as the previous one is answered or timed
+    /// out, but no sooner than the interval after it, instead of every interval, like
+    /// `ping -A`: the cadence follows the round trip time and the interval bounds the rate.
+    /// (default: false)
+    pub fn adaptive(mut self, adaptive: bool) -> Self {
+        self.adaptive = adaptive;
+        self
+    }
+
     pub fn fib(mut self, fib: u32) -> Self {
         self.fib = Some(fib);
         self
@@ -116,6 +620,40 @@ impl ConfigBuilder {
             bind: self.bind,
             interface: self.interface,
             ttl: self.ttl,
+            hop_limit: self.hop_limit,
+            tos: self.tos,
+            dont_fragment: self.dont_fragment,
+            verify_payload: self.verify_payload,
+            recv_batch_size: self.recv_batch_size,
+            allow_flood: self.allow_flood,
+            fwmark: self.fwmark,
+            recv_buffer_size: self.recv_buffer_size,
+            send_buffer_size: self.send_buffer_size,
+            max_pps: self.max_pps,
+            waiter_ttl: self.waiter_ttl,
+            broadcast: self.broadcast,
+            multicast_hops: self.multicast_hops,
+            multicast_interface: self.multicast_interface,
+            identifier_strategy: self.identifier_strategy,
+            embed_timestamp: self.embed_timestamp,
+            verify_checksum: self.verify_checksum,
+            read_timeout: self.read_timeout,
+            icmp_type_filter: self.icmp_type_filter,
+            manual_recv: self.manual_recv,
+            reuse_address: self.reuse_address,
+            reuse_port: self.reuse_port,
+            priority: self.priority,
+            record_route: self.record_route,
+            match_policy: self.match_policy,
+            enforce_mtu: self.enforce_mtu,
+            hardware_timestamps: self.hardware_timestamps,
+            bind_port: self.bind_port,
+            zero_based_sequence: self.zero_based_sequence,
+            icmpv6_checksum: self.icmpv6_checksum,
+            send_pool: self.send_pool,
+            adaptive: self.adaptive,
+            flow_label: self.flow_label,
+            allow_shared_waiters: self.allow_shared_waiters,
             fib: self.fib,
         }
     }
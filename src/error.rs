@@ -3,7 +3,7 @@ use std::{io, net::IpAddr};
 
 use thiserror::Error;
 
-use crate::{icmp::PingSequence, PingIdentifier};
+use crate::{icmp::PingSequence, PingIdentifier, ICMP};
 
 pub type Result<T> = std::result::Result<T, SurgeError>;
 
@@ -19,8 +19,19 @@ pub enum SurgeError {
     IOError(#[from] io::Error),
     #[error("Echo Request packet.")]
     EchoRequestPacket,
+    /// An ICMP message neither answering nor reporting an error about an echo request,
+    /// e.g. an ICMPv6 neighbor discovery message.
+    #[error("ICMP packet unrelated to pings")]
+    NotPingRelated,
+    /// The reply waiter was dropped because the `Client` receiving replies was dropped.
     #[error("Network error.")]
     NetworkError,
+    /// The request was made after, or was still pending at the end of, `Client::shutdown`.
+    #[error("Client is shut down")]
+    ClientShutdown,
+    /// The echo request does not fit in the MTU, see `Config::builder().enforce_mtu()`.
+    #[error("Packet of {size} bytes exceeds the MTU of {mtu} bytes")]
+    ExceedsMtu { size: usize, mtu: usize },
     #[error("Multiple identical request")]
     IdenticalRequests {
         host: IpAddr,
@@ -29,6 +40,62 @@ pub enum SurgeError {
     },
     #[error("Unsupported sequence number")]
     UnsupportedSeqNum,
+    #[error("Request timeout for {host} icmp_seq {seq}")]
+    Timeout { host: IpAddr, seq: PingSequence },
+    /// The request was abandoned with `Pinger::cancel`.
+    #[error("Request cancelled for {host} icmp_seq {seq}")]
+    Cancelled { host: IpAddr, seq: PingSequence },
+    /// The reply did not echo the payload of the request, see `Config::builder().verify_payload()`.
+    #[error("Payload mismatch, sent {expected_len} bytes, got {got_len} bytes back")]
+    PayloadMismatch { expected_len: usize, got_len: usize },
+    /// Neither a raw nor a datagram ICMP socket may be opened, see `Client::probe_support`.
+    #[error(
+        "permission denied opening an ICMP {kind:?} socket: run as root, grant the binary \
+         CAP_NET_RAW (setcap cap_net_raw+ep <binary>) or, on Linux, allow datagram ICMP \
+         sockets for your group (sysctl net.ipv4.ping_group_range): {source}"
+    )]
+    InsufficientPrivileges { kind: ICMP, source: io::Error },
+    /// The host is not of the address family of the client, e.g. an IPv6 host pinged by an
+    /// ICMP `V4` client, see `Client::try_pinger`. IPv4-mapped IPv6 addresses count as IPv6.
+    #[error("cannot ping {host} from an ICMP {kind:?} client")]
+    AddressFamilyMismatch { host: IpAddr, kind: ICMP },
+    /// The socket given to `Client::from_socket` cannot be used to send `kind` pings.
+    #[error("socket is not an ICMP {kind:?} socket")]
+    SocketMismatch { kind: ICMP },
+    /// See `Config::builder().embed_timestamp()`.
+    #[error("Payload of {len} bytes is too short to embed an 8 bytes timestamp")]
+    PayloadTooShortForTimestamp { len: usize },
+    /// The network has more addresses than `Client::sweep_cidr` pings, see
+    /// `Client::SWEEP_MAX_HOSTS`.
+    #[error("{cidr} has more than {max} addresses")]
+    NetworkTooLarge { cidr: ipnet::IpNet, max: u32 },
+    #[error("Only supported for IPv4 hosts")]
+    Ipv4Only,
+    #[error("Only supported for IPv6 hosts")]
+    Ipv6Only,
+    #[error("Flood ping must be enabled with Config::builder().allow_flood(true)")]
+    FloodNotAllowed,
+    /// A router discarded the echo request. Only reported on raw sockets, Linux ICMP
+    /// sockets do not deliver ICMP error messages through `recv`.
+    #[error("Time exceeded (code {code}) reported by {responder}")]
+    TimeExceeded { responder: IpAddr, code: u8 },
+    /// The reply did not have a valid ICMP checksum, see `Config::builder().verify_checksum()`.
+    #[error("Bad ICMP checksum")]
+    BadChecksum,
+    /// A router or the host reported the destination as unreachable. Only reported on raw
+    /// sockets. `code` tells why, e.g. for IPv4 0 network, 1 host, 3 port or 13
+    /// administratively prohibited; for IPv6 0 no route, 1 administratively prohibited,
+    /// 3 address or 4 port unreachable.
+    #[error("Destination unreachable (code {code}) reported by {responder}")]
+    Unreachable { responder: IpAddr, code: u8 },
+    /// A router could not forward the echo request without fragmenting it and the
+    /// Don't Fragment bit was set. `mtu` is the next-hop MTU it advertised (0 if unknown).
+    #[error("Fragmentation needed, next-hop MTU {mtu} reported by {responder}")]
+    FragmentationNeeded { responder: IpAddr, mtu: u16 },
+    /// A router could not forward the IPv6 echo request, larger than the MTU of the next
+    /// link which it advertised.
+    #[error("Packet too big, MTU {mtu} reported by {responder}")]
+    PacketTooBig { responder: IpAddr, mtu: u32 },
 }
 
 #[derive(Error, Debug)]
@@ -41,6 +108,8 @@ pub enum MalformedPacketError {
     NotIcmpv4Packet,
     #[error("expected an Icmpv6Packet")]
     NotIcmpv6Packet,
+    #[error("expected an Icmpv4 Timestamp Reply")]
+    NotTimestampReply,
     #[error("payload too short, got {got}, want {want}")]
     PayloadTooShort { got: usize, want: usize },
 }
@@ -0,0 +1,299 @@
+//! RFC 4884 extensions of ICMP error messages, such as the MPLS label stack (RFC 4950) or
+//! the interface information (RFC 5837) routers attach to Time Exceeded messages.
+
+use std::convert::{TryFrom, TryInto};
+use std::net::IpAddr;
+
+use pnet_packet::util;
+
+/// Extensions start after the original datagram, padded to at least this many bytes.
+const MIN_ORIGINAL_DATAGRAM_LEN: usize = 128;
+const EXTENSION_VERSION: u8 = 2;
+
+const CLASS_MPLS_LABEL_STACK: u8 = 1;
+const CLASS_INTERFACE_INFORMATION: u8 = 2;
+
+/// The extension objects of an ICMP error message, see [`Icmpv4Packet::extensions`].
+///
+/// [`Icmpv4Packet::extensions`]: super::icmpv4::Icmpv4Packet::extensions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IcmpExtensions {
+    pub objects: Vec<IcmpExtensionObject>,
+}
+
+/// An extension object, by class.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IcmpExtensionObject {
+    /// The label stack of the packet when it expired, top label first (RFC 4950).
+    MplsLabelStack(Vec<MplsLabel>),
+    /// An interface of the router which sent the message (RFC 5837).
+    InterfaceInformation(InterfaceInformation),
+    /// An object of another class, or with a C-Type this crate does not know.
+    Unknown {
+        class_num: u8,
+        c_type: u8,
+        payload: Vec<u8>,
+    },
+}
+
+/// An MPLS label stack entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MplsLabel {
+    /// 20 bits.
+    pub label: u32,
+    /// Traffic class, formerly EXP. 3 bits.
+    pub traffic_class: u8,
+    pub bottom_of_stack: bool,
+    pub ttl: u8,
+}
+
+/// Which interface an [`InterfaceInformation`] object describes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterfaceRole {
+    /// The interface the packet arrived on.
+    Incoming,
+    /// The sub-IP component, e.g. a member of a bundle, of the incoming interface.
+    SubIpComponent,
+    /// The interface the packet would have been forwarded on.
+    Outgoing,
+    /// The next hop the packet would have been forwarded to.
+    NextHop,
+}
+
+/// An interface of a router, each field is optional.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceInformation {
+    pub role: InterfaceRole,
+    pub if_index: Option<u32>,
+    pub address: Option<IpAddr>,
+    pub name: Option<String>,
+    pub mtu: Option<u32>,
+}
+
+impl IcmpExtensions {
+    /// Parse the extensions of an ICMPv4 Time Exceeded, Destination Unreachable or Parameter
+    /// Problem message, header included. `None` if there are none or they are malformed.
+    pub fn parse_v4(message: &[u8]) -> Option<Self> {
+        // The length of the original datagram is counted in 32-bit words.
+        Self::parse(message, 4)
+    }
+
+    /// Parse the extensions of an ICMPv6 Time Exceeded or Destination Unreachable message,
+    /// header included. `None` if there are none or they are malformed.
+    pub fn parse_v6(message: &[u8]) -> Option<Self> {
+        // The length of the original datagram is counted in 64-bit words.
+        Self::parse(message, 8)
+    }
+
+    fn parse(message: &[u8], word_len: usize) -> Option<Self> {
+        let original_len = match usize::from(*message.get(4)?) * word_len {
+            // Routers predating RFC 4884 leave the length out and put the extensions after
+            // exactly 128 bytes, as traceroute implementations assume.
+            0 => MIN_ORIGINAL_DATAGRAM_LEN,
+            len if len < MIN_ORIGINAL_DATAGRAM_LEN => return None,
+            len => len,
+        };
+        let structure = message.get(8 + original_len..)?;
+        if structure.len() < 4 || structure[0] >> 4 != EXTENSION_VERSION {
+            return None;
+        }
+        let checksum = u16::from_be_bytes(structure[2..4].try_into().unwrap());
+        if checksum != util::checksum(structure, 1) {
+            return None;
+        }
+
+        let mut objects = Vec::new();
+        let mut rest = &structure[4..];
+        while !rest.is_empty() {
+            // length(2) + class-num(1) + c-type(1), the length includes this header.
+            let len = usize::from(u16::from_be_bytes(rest.get(0..2)?.try_into().unwrap()));
+            if len < 4 || len > rest.len() {
+                return None;
+            }
+            objects.push(IcmpExtensionObject::parse(rest[2], rest[3], &rest[4..len])?);
+            rest = &rest[len..];
+        }
+        Some(IcmpExtensions { objects })
+    }
+
+    /// The MPLS label stack, if there is one.
+    pub fn mpls_labels(&self) -> Option<&[MplsLabel]> {
+        self.objects.iter().find_map(|object| match object {
+            IcmpExtensionObject::MplsLabelStack(labels) => Some(&labels[..]),
+            _ => None,
+        })
+    }
+
+    /// The interface information objects.
+    pub fn interfaces(&self) -> impl Iterator<Item = &InterfaceInformation> {
+        self.objects.iter().filter_map(|object| match object {
+            IcmpExtensionObject::InterfaceInformation(interface) => Some(interface),
+            _ => None,
+        })
+    }
+}
+
+impl IcmpExtensionObject {
+    fn parse(class_num: u8, c_type: u8, payload: &[u8]) -> Option<Self> {
+        Some(match (class_num, c_type) {
+            (CLASS_MPLS_LABEL_STACK, 1) => {
+                let entries = payload.chunks_exact(4);
+                if !entries.remainder().is_empty() {
+                    return None;
+                }
+                IcmpExtensionObject::MplsLabelStack(
+                    entries
+                        .map(|entry| {
+                            let entry = u32::from_be_bytes(entry.try_into().unwrap());
+                            MplsLabel {
+                                label: entry >> 12,
+                                traffic_class: (entry >> 9 & 0x7) as u8,
+                                bottom_of_stack: entry & 0x100 != 0,
+                                ttl: entry as u8,
+                            }
+                        })
+                        .collect(),
+                )
+            }
+            (CLASS_INTERFACE_INFORMATION, _) => IcmpExtensionObject::InterfaceInformation(
+                InterfaceInformation::parse(c_type, payload)?,
+            ),
+            _ => IcmpExtensionObject::Unknown {
+                class_num,
+                c_type,
+                payload: payload.to_vec(),
+            },
+        })
+    }
+}
+
+impl InterfaceInformation {
+    /// The C-Type tells the role and which fields follow, in order: ifIndex, IP address,
+    /// name and MTU.
+    fn parse(c_type: u8, mut payload: &[u8]) -> Option<Self> {
+        let mut take = |len: usize| {
+            let field = payload.get(..len)?;
+            payload = &payload[len..];
+            Some(field)
+        };
+        let role = match c_type >> 6 {
+            0 => InterfaceRole::Incoming,
+            1 => InterfaceRole::SubIpComponent,
+            2 => InterfaceRole::Outgoing,
+            _ => InterfaceRole::NextHop,
+        };
+        let if_index = match c_type & 0x08 {
+            0 => None,
+            _ => Some(u32::from_be_bytes(take(4)?.try_into().unwrap())),
+        };
+        let address = match c_type & 0x04 {
+            0 => None,
+            // AFI(2) + reserved(2) + address
+            _ => match u16::from_be_bytes(take(4)?[..2].try_into().unwrap()) {
+                1 => Some(IpAddr::from(<[u8; 4]>::try_from(take(4)?).unwrap())),
+                2 => Some(IpAddr::from(<[u8; 16]>::try_from(take(16)?).unwrap())),
+                _ => return None,
+            },
+        };
+        let name = match c_type & 0x02 {
+            0 => None,
+            // length(1), which counts itself and the padding to 32 bits, + UTF-8 name
+            _ => {
+                let len = usize::from(*take(1)?.first()?);
+                if len == 0 || len & 3 != 0 {
+                    return None;
+                }
+                let name = take(len - 1)?;
+                let end = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+                Some(String::from_utf8_lossy(&name[..end]).into_owned())
+            }
+        };
+        let mtu = match c_type & 0x01 {
+            0 => None,
+            _ => Some(u32::from_be_bytes(take(4)?.try_into().unwrap())),
+        };
+        Some(InterfaceInformation {
+            role,
+            if_index,
+            address,
+            name,
+            mtu,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A Time Exceeded message quoting 128 bytes, with `length` as the length field.
+    fn time_exceeded(length: u8, objects: &[u8]) -> Vec<u8> {
+        let mut message = vec![11, 0, 0, 0, 0, length, 0, 0];
+        message.extend_from_slice(&[0; 128]);
+        let mut structure = vec![0x20, 0, 0, 0];
+        structure.extend_from_slice(objects);
+        let checksum = util::checksum(&structure, 1);
+        structure[2..4].copy_from_slice(&checksum.to_be_bytes());
+        message.extend_from_slice(&structure);
+        message
+    }
+
+    #[test]
+    fn mpls_and_interface() {
+        let objects = hex::decode(concat!(
+            // MPLS label stack: label 16000, TC 0, bottom of stack, TTL 1
+            "00080101",
+            "03e80101",
+            // incoming interface with ifIndex, IPv4 address, name and MTU
+            "001c020f",
+            "00000004",
+            "00010000c0000201",
+            "0865746830000000",
+            "000005dc",
+        ))
+        .unwrap();
+        let extensions = IcmpExtensions::parse_v4(&time_exceeded(32, &objects)).unwrap();
+        assert_eq!(
+            extensions.mpls_labels(),
+            Some(
+                &[MplsLabel {
+                    label: 16000,
+                    traffic_class: 0,
+                    bottom_of_stack: true,
+                    ttl: 1,
+                }][..]
+            )
+        );
+        assert_eq!(
+            extensions.interfaces().collect::<Vec<_>>(),
+            [&InterfaceInformation {
+                role: InterfaceRole::Incoming,
+                if_index: Some(4),
+                address: Some("192.0.2.1".parse().unwrap()),
+                name: Some("eth0".to_string()),
+                mtu: Some(1500),
+            }]
+        );
+
+        // Without the length field, as sent by routers predating RFC 4884.
+        assert_eq!(
+            IcmpExtensions::parse_v4(&time_exceeded(0, &objects)),
+            Some(extensions)
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        let mut message = time_exceeded(32, &hex::decode("0008010103e80101").unwrap());
+        // No extensions after a shorter original datagram.
+        assert_eq!(IcmpExtensions::parse_v4(&message[..136]), None);
+        // An object longer than the structure.
+        assert_eq!(
+            IcmpExtensions::parse_v4(&time_exceeded(32, &hex::decode("000c0101").unwrap())),
+            None
+        );
+        // A bad checksum.
+        *message.last_mut().unwrap() ^= 1;
+        assert_eq!(IcmpExtensions::parse_v4(&message), None);
+    }
+}
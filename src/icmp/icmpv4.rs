@@ -2,8 +2,10 @@ use socket2::Type as SockType;
 use std::convert::TryInto;
 use std::net::Ipv4Addr;
 use std::num::NonZeroU16;
+use std::ops::Range;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use pnet_packet::icmp::{self, IcmpCode, IcmpType};
+use pnet_packet::icmp::{self, destination_unreachable, IcmpCode, IcmpType};
 use pnet_packet::Packet;
 use pnet_packet::{ipv4, PacketSize};
 
@@ -12,22 +14,23 @@ use crate::{
     is_linux_icmp_socket,
 };
 
-use super::{PingIdentifier, PingSequence};
+use super::{extensions::IcmpExtensions, PingIdentifier, PingSequence, ICMP_HEADER_LEN};
 
-pub fn make_icmpv4_echo_packet(
+/// Build an echo request into `buf`, zeroed and [`ICMP_HEADER_LEN`] bytes longer than
+/// `payload`. On Linux ICMP sockets the identifier and checksum are left to the kernel.
+pub(crate) fn write_icmpv4_echo_packet(
+    buf: &mut [u8],
     ident_hint: PingIdentifier,
-    seq_cnt: PingSequence,
+    seq_cnt: u16,
     sock_type: SockType,
     payload: &[u8],
-) -> Result<Vec<u8>> {
-    // 8 bytes of header, then payload.
-    let mut buf = vec![0; 8 + payload.len()];
-    let mut packet = icmp::echo_request::MutableEchoRequestPacket::new(&mut buf[..])
+) -> Result<()> {
+    let mut packet = icmp::echo_request::MutableEchoRequestPacket::new(buf)
         .ok_or(SurgeError::IncorrectBufferSize)?;
 
     packet.set_icmp_type(icmp::IcmpTypes::EchoRequest);
     packet.set_payload(payload);
-    packet.set_sequence_number(seq_cnt.into_u16());
+    packet.set_sequence_number(seq_cnt);
 
     if !(is_linux_icmp_socket!(sock_type)) {
         packet.set_identifier(ident_hint.into_u16());
@@ -40,21 +43,172 @@ pub fn make_icmpv4_echo_packet(
         packet.set_checksum(checksum);
     }
 
+    Ok(())
+}
+
+/// Most addresses an IPv4 Record Route option can hold: the IP options are limited to
+/// 40 bytes, 3 of which are the option header.
+pub const RECORD_ROUTE_MAX_HOPS: usize = 9;
+
+const IPOPT_END: u8 = 0;
+const IPOPT_NOP: u8 = 1;
+const IPOPT_RR: u8 = 7;
+
+/// Build an empty IPv4 Record Route option with room for [`RECORD_ROUTE_MAX_HOPS`]
+/// addresses, padded to the 40 bytes of IP options. It is not part of the ICMP message:
+/// the kernel puts it in the IP header of the requests, see [`crate::ConfigBuilder::record_route`].
+pub(crate) fn record_route_option() -> [u8; 40] {
+    let mut option = [IPOPT_END; 40];
+    // type, length, pointer to the first free slot (1-based)
+    option[0] = IPOPT_RR;
+    option[1] = (3 + 4 * RECORD_ROUTE_MAX_HOPS) as u8;
+    option[2] = 4;
+    option
+}
+
+/// Read the addresses filled in the Record Route option of IPv4 header options, if any.
+/// A malformed or truncated option yields the addresses that fit.
+fn decode_recorded_route(mut options: &[u8]) -> Vec<Ipv4Addr> {
+    while let Some(&kind) = options.first() {
+        match kind {
+            IPOPT_END => break,
+            IPOPT_NOP => options = &options[1..],
+            _ => {
+                let len = match options.get(1) {
+                    Some(&len) if len >= 2 => usize::from(len).min(options.len()),
+                    _ => break,
+                };
+                if kind == IPOPT_RR && len >= 3 {
+                    // The pointer is past the last recorded address.
+                    let end = usize::from(options[2]).saturating_sub(1).min(len);
+                    return options
+                        .get(3..end)
+                        .unwrap_or_default()
+                        .chunks_exact(4)
+                        .map(|addr| Ipv4Addr::new(addr[0], addr[1], addr[2], addr[3]))
+                        .collect();
+                }
+                options = &options[len..];
+            }
+        }
+    }
+    Vec::new()
+}
+
+/// Build an ICMP Timestamp Request. As for echo requests, on Linux ICMP sockets the
+/// identifier and checksum are left to the kernel.
+pub fn make_icmpv4_timestamp_packet(
+    ident_hint: PingIdentifier,
+    seq_cnt: u16,
+    sock_type: SockType,
+    originate: u32,
+) -> Result<Vec<u8>> {
+    // 8 bytes of header, then originate, receive and transmit timestamps.
+    let mut buf = [0; 20];
+    let mut packet =
+        icmp::MutableIcmpPacket::new(&mut buf[..]).ok_or(SurgeError::IncorrectBufferSize)?;
+    packet.set_icmp_type(icmp::IcmpTypes::Timestamp);
+
+    // identifier(2) + sequence(2) + originate(4), receive and transmit are left zeroed.
+    let mut body = [0; 16];
+    body[2..4].copy_from_slice(&seq_cnt.to_be_bytes());
+    body[4..8].copy_from_slice(&originate.to_be_bytes());
+    if is_linux_icmp_socket!(sock_type) {
+        packet.set_payload(&body);
+    } else {
+        body[0..2].copy_from_slice(&ident_hint.into_u16().to_be_bytes());
+        packet.set_payload(&body);
+        let checksum = icmp::checksum(&packet.to_immutable());
+        packet.set_checksum(checksum);
+    }
+
     Ok(packet.packet().to_vec())
 }
 
+/// Milliseconds elapsed since midnight UTC, the time format of ICMP Timestamp messages.
+pub fn ms_since_midnight() -> u32 {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    (since_epoch.as_millis() % 86_400_000) as u32
+}
+
+/// Times carried by an ICMP Timestamp Reply, in milliseconds since midnight UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IcmpTimestamps {
+    /// When the request was sent by us.
+    pub originate: u32,
+    /// When the request was received by the remote host.
+    pub receive: u32,
+    /// When the reply was sent by the remote host.
+    pub transmit: u32,
+}
+
+/// Whether the ICMP checksum of a received message, as passed to [`Icmpv4Packet::decode`],
+/// is correct.
+pub(crate) fn checksum_ok(buf: &[u8], sock_type: SockType) -> bool {
+    let ipv4_packet;
+    let icmp_buf = if is_linux_icmp_socket!(sock_type) {
+        buf
+    } else {
+        match ipv4::Ipv4Packet::new(buf) {
+            Some(packet) => {
+                ipv4_packet = packet;
+                ipv4_packet.payload()
+            }
+            None => return false,
+        }
+    };
+    match icmp::IcmpPacket::new(icmp_buf) {
+        Some(packet) => icmp::checksum(&packet) == packet.get_checksum(),
+        None => false,
+    }
+}
+
+/// The destination, identifier and sequence number of the echo request quoted by an ICMP
+/// error message, from the payload following the ICMP header. The quoted IPv4 header may
+/// carry options, its length is read from its IHL field.
+fn decode_original_echo(icmp_payload: &[u8]) -> Result<(Ipv4Addr, u16, u16)> {
+    // icmp unused(4) + ip header(ihl * 4) + echo icmp(8). An IHL below the minimum of 5
+    // words is bogus, assume a header without options then.
+    let ip_header_len = icmp_payload
+        .get(4)
+        .map_or(20, |version_ihl| usize::from(version_ihl & 0x0f) * 4)
+        .max(20);
+    let want = 4 + ip_header_len + 8;
+    if icmp_payload.len() < want {
+        return Err(SurgeError::from(MalformedPacketError::PayloadTooShort {
+            got: icmp_payload.len(),
+            want,
+        }));
+    }
+    let real_ip_packet = ipv4::Ipv4Packet::new(&icmp_payload[4..])
+        .ok_or_else(|| SurgeError::from(MalformedPacketError::NotIpv4Packet))?;
+    let echo = &icmp_payload[4 + ip_header_len..];
+    let identifier = u16::from_be_bytes(echo[4..6].try_into().unwrap());
+    let sequence = u16::from_be_bytes(echo[6..8].try_into().unwrap());
+    Ok((real_ip_packet.get_destination(), identifier, sequence))
+}
+
 /// Packet structure returned by ICMPv4.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Icmpv4Packet {
     source: Ipv4Addr,
     destination: Ipv4Addr,
     ttl: Option<u8>,
+    ip_id: Option<u16>,
     icmp_type: IcmpType,
     icmp_code: IcmpCode,
     size: usize,
     real_dest: Ipv4Addr,
     identifier: PingIdentifier,
     sequence: PingSequence,
+    /// The data of an echo reply, within `raw`.
+    payload: Range<usize>,
+    next_hop_mtu: Option<u16>,
+    timestamps: Option<IcmpTimestamps>,
+    recorded_route: Vec<Ipv4Addr>,
+    raw: Vec<u8>,
 }
 
 impl Default for Icmpv4Packet {
@@ -63,12 +217,18 @@ impl Default for Icmpv4Packet {
             source: Ipv4Addr::new(127, 0, 0, 1),
             destination: Ipv4Addr::new(127, 0, 0, 1),
             ttl: None,
+            ip_id: None,
             icmp_type: IcmpType::new(0),
             icmp_code: IcmpCode::new(0),
             size: 0,
             real_dest: Ipv4Addr::new(127, 0, 0, 1),
             identifier: PingIdentifier(0),
             sequence: PingSequence(NonZeroU16::new(1).unwrap()),
+            payload: 0..0,
+            next_hop_mtu: None,
+            timestamps: None,
+            recorded_route: Vec::new(),
+            raw: Vec::new(),
         }
     }
 }
@@ -94,16 +254,32 @@ impl Icmpv4Packet {
         self.destination
     }
 
-    fn ttl(&mut self, ttl: u8) -> &mut Self {
+    pub(crate) fn ttl(&mut self, ttl: u8) -> &mut Self {
         self.ttl = Some(ttl);
         self
     }
 
     /// Get the ttl field.
+    ///
+    /// On raw sockets it is read from the IP header, on Linux ICMP sockets it comes from
+    /// the `IP_TTL` control message. `None` if it could not be determined.
     pub fn get_ttl(&self) -> Option<u8> {
         self.ttl
     }
 
+    fn ip_id(&mut self, ip_id: u16) -> &mut Self {
+        self.ip_id = Some(ip_id);
+        self
+    }
+
+    /// Get the Identification field of the IPv4 header.
+    ///
+    /// Only available on raw sockets, `None` on Linux ICMP sockets which do not deliver
+    /// the IP header.
+    pub fn get_ip_id(&self) -> Option<u16> {
+        self.ip_id
+    }
+
     fn icmp_type(&mut self, icmp_type: IcmpType) -> &mut Self {
         self.icmp_type = icmp_type;
         self
@@ -129,7 +305,7 @@ impl Icmpv4Packet {
         self
     }
 
-    /// Get the size of the icmp_v4 packet.
+    /// Get the size of the icmp_v4 packet, header included, see [`echo_payload_len`](super::echo_payload_len).
     pub fn get_size(&self) -> usize {
         self.size
     }
@@ -150,7 +326,9 @@ impl Icmpv4Packet {
         self
     }
 
-    /// Get the identifier of the icmp_v4 packet.
+    /// Get the identifier of the icmp_v4 packet: of the echo reply, or of the request quoted
+    /// by an error message. On Linux ICMP sockets, the one the kernel assigned, which is
+    /// the port of the socket.
     pub fn get_identifier(&self) -> PingIdentifier {
         self.identifier
     }
@@ -165,21 +343,99 @@ impl Icmpv4Packet {
         self.sequence
     }
 
+    fn payload(&mut self, payload: Range<usize>) -> &mut Self {
+        self.payload = payload;
+        self
+    }
+
+    /// Get the data echoed back by an echo reply, empty for other messages.
+    pub fn get_payload(&self) -> &[u8] {
+        &self.raw[self.payload.clone()]
+    }
+
+    fn next_hop_mtu(&mut self, mtu: u16) -> &mut Self {
+        self.next_hop_mtu = Some(mtu);
+        self
+    }
+
+    /// For a "fragmentation needed" destination unreachable message, the MTU of the
+    /// next-hop network as advertised by the router.
+    pub fn get_next_hop_mtu(&self) -> Option<u16> {
+        self.next_hop_mtu
+    }
+
+    fn timestamps(&mut self, timestamps: IcmpTimestamps) -> &mut Self {
+        self.timestamps = Some(timestamps);
+        self
+    }
+
+    /// For a timestamp reply, the originate, receive and transmit timestamps.
+    pub fn get_timestamps(&self) -> Option<IcmpTimestamps> {
+        self.timestamps
+    }
+
+    fn recorded_route(&mut self, recorded_route: Vec<Ipv4Addr>) -> &mut Self {
+        self.recorded_route = recorded_route;
+        self
+    }
+
+    /// The addresses in the Record Route option of the reply, at most
+    /// [`RECORD_ROUTE_MAX_HOPS`]: the hops to the host and back, as far as there was room.
+    /// Empty if the option was not requested or the IP header is not available (Linux ICMP
+    /// sockets).
+    pub fn get_recorded_route(&self) -> Vec<Ipv4Addr> {
+        self.recorded_route.clone()
+    }
+
+    fn raw(&mut self, raw: &[u8]) -> &mut Self {
+        self.raw = raw.to_vec();
+        self
+    }
+
+    /// Get the whole ICMP message as received, header included, to parse what the typed
+    /// accessors do not cover, such as RFC 4884 extensions.
+    pub fn raw_bytes(&self) -> &[u8] {
+        &self.raw
+    }
+
+    /// The RFC 4884 extensions of a Time Exceeded, Destination Unreachable or Parameter
+    /// Problem message, such as the MPLS label stack, see [`IcmpExtensions::parse_v4`].
+    /// Such messages are handed over by awaiting the waiter of `Pinger::ping_send`.
+    pub fn extensions(&self) -> Option<IcmpExtensions> {
+        match self.icmp_type {
+            icmp::IcmpTypes::TimeExceeded
+            | icmp::IcmpTypes::DestinationUnreachable
+            | icmp::IcmpTypes::ParameterProblem => IcmpExtensions::parse_v4(&self.raw),
+            _ => None,
+        }
+    }
+
     /// Decode into icmp packet from the socket message.
     pub fn decode(
         buf: &[u8],
         sock_type: SockType,
         src_addr: Ipv4Addr,
         dst_addr: Ipv4Addr,
+    ) -> Result<Self> {
+        Self::decode_with(buf, sock_type, src_addr, dst_addr, false)
+    }
+
+    /// [`decode`](Self::decode), see `ConfigBuilder::zero_based_sequence` for `zero_based`.
+    pub(crate) fn decode_with(
+        buf: &[u8],
+        sock_type: SockType,
+        src_addr: Ipv4Addr,
+        dst_addr: Ipv4Addr,
+        zero_based: bool,
     ) -> Result<Self> {
         if is_linux_icmp_socket!(sock_type) {
-            Self::decode_from_icmp(buf, src_addr, dst_addr)
+            Self::decode_from_icmp(buf, src_addr, dst_addr, zero_based)
         } else {
-            Self::decode_from_ipv4(buf)
+            Self::decode_from_ipv4(buf, zero_based)
         }
     }
 
-    fn decode_from_ipv4(buf: &[u8]) -> Result<Self> {
+    fn decode_from_ipv4(buf: &[u8], zero_based: bool) -> Result<Self> {
         let ipv4_packet = ipv4::Ipv4Packet::new(buf)
             .ok_or_else(|| SurgeError::from(MalformedPacketError::NotIpv4Packet))?;
         let icmp_packet = icmp::IcmpPacket::new(ipv4_packet.payload())
@@ -198,29 +454,51 @@ impl Icmpv4Packet {
                     .icmp_type(icmp_packet.get_icmp_type())
                     .icmp_code(icmp_packet.get_icmp_code())
                     .size(icmp_packet.packet().len())
+                    .payload(ICMP_HEADER_LEN..icmp_packet.packet().len())
                     .real_dest(ipv4_packet.get_source())
                     .identifier(icmp_packet.get_identifier().into())
-                    .sequence(
-                        NonZeroU16::new(icmp_packet.get_sequence_number())
-                            .ok_or_else(|| SurgeError::UnsupportedSeqNum)?
-                            .into(),
-                    );
+                    .sequence(PingSequence::from_wire(
+                        icmp_packet.get_sequence_number(),
+                        zero_based,
+                    )?);
             }
             icmp::IcmpTypes::EchoRequest => return Err(SurgeError::EchoRequestPacket),
-            _ => {
+            // Our own timestamp request, looped back on a raw socket.
+            icmp::IcmpTypes::Timestamp => return Err(SurgeError::EchoRequestPacket),
+            icmp::IcmpTypes::TimestampReply => {
                 let icmp_payload = icmp_packet.payload();
 
-                if icmp_payload.len() < 32 {
+                if icmp_payload.len() < 16 {
                     return Err(SurgeError::from(MalformedPacketError::PayloadTooShort {
                         got: icmp_payload.len(),
-                        want: 32,
+                        want: 16,
                     }));
                 }
-                // icmp unused(4) + ip header(20) + echo icmp(4)
-                let real_ip_packet = ipv4::Ipv4Packet::new(&icmp_payload[4..])
-                    .ok_or_else(|| SurgeError::from(MalformedPacketError::NotIpv4Packet))?;
-                let identifier = u16::from_be_bytes(icmp_payload[28..30].try_into().unwrap());
-                let sequence = u16::from_be_bytes(icmp_payload[30..32].try_into().unwrap());
+                // identifier(2) + sequence(2) + originate(4) + receive(4) + transmit(4)
+                let identifier = u16::from_be_bytes(icmp_payload[0..2].try_into().unwrap());
+                let sequence = u16::from_be_bytes(icmp_payload[2..4].try_into().unwrap());
+                let timestamp =
+                    |at: usize| u32::from_be_bytes(icmp_payload[at..at + 4].try_into().unwrap());
+
+                packet
+                    .source(ipv4_packet.get_source())
+                    .destination(ipv4_packet.get_destination())
+                    .ttl(ipv4_packet.get_ttl())
+                    .icmp_type(icmp_packet.get_icmp_type())
+                    .icmp_code(icmp_packet.get_icmp_code())
+                    .size(icmp_packet.packet_size())
+                    .real_dest(ipv4_packet.get_source())
+                    .identifier(identifier.into())
+                    .sequence(PingSequence::from_wire(sequence, zero_based)?)
+                    .timestamps(IcmpTimestamps {
+                        originate: timestamp(4),
+                        receive: timestamp(8),
+                        transmit: timestamp(12),
+                    });
+            }
+            _ => {
+                let icmp_payload = icmp_packet.payload();
+                let (real_dest, identifier, sequence) = decode_original_echo(icmp_payload)?;
 
                 packet
                     .source(ipv4_packet.get_source())
@@ -229,20 +507,33 @@ impl Icmpv4Packet {
                     .icmp_type(icmp_packet.get_icmp_type())
                     .icmp_code(icmp_packet.get_icmp_code())
                     .size(icmp_packet.packet_size())
-                    .real_dest(real_ip_packet.get_destination())
+                    .real_dest(real_dest)
                     .identifier(identifier.into())
-                    .sequence(
-                        NonZeroU16::new(sequence)
-                            .ok_or_else(|| SurgeError::UnsupportedSeqNum)?
-                            .into(),
-                    );
+                    .sequence(PingSequence::from_wire(sequence, zero_based)?);
+
+                if icmp_packet.get_icmp_type() == icmp::IcmpTypes::DestinationUnreachable
+                    && icmp_packet.get_icmp_code()
+                        == destination_unreachable::IcmpCodes::FragmentationRequiredAndDFFlagSet
+                {
+                    // icmp unused(2) + next-hop mtu(2)
+                    packet.next_hop_mtu(u16::from_be_bytes(icmp_payload[2..4].try_into().unwrap()));
+                }
             }
         }
+        packet
+            .ip_id(ipv4_packet.get_identification())
+            .recorded_route(decode_recorded_route(ipv4_packet.get_options_raw()))
+            .raw(icmp_packet.packet());
 
         Ok(packet)
     }
 
-    fn decode_from_icmp(buf: &[u8], src_addr: Ipv4Addr, dst_addr: Ipv4Addr) -> Result<Self> {
+    fn decode_from_icmp(
+        buf: &[u8],
+        src_addr: Ipv4Addr,
+        dst_addr: Ipv4Addr,
+        zero_based: bool,
+    ) -> Result<Self> {
         let icmp_packet = icmp::IcmpPacket::new(buf)
             .ok_or_else(|| SurgeError::from(MalformedPacketError::NotIcmpv4Packet))?;
         let mut packet = Icmpv4Packet::default();
@@ -258,30 +549,18 @@ impl Icmpv4Packet {
                     .icmp_type(icmp_packet.get_icmp_type())
                     .icmp_code(icmp_packet.get_icmp_code())
                     .size(icmp_packet.packet().len())
+                    .payload(ICMP_HEADER_LEN..icmp_packet.packet().len())
                     .real_dest(src_addr)
                     .identifier(icmp_packet.get_identifier().into())
-                    .sequence(
-                        NonZeroU16::new(icmp_packet.get_sequence_number())
-                            .ok_or_else(|| SurgeError::UnsupportedSeqNum)?
-                            .into(),
-                    );
+                    .sequence(PingSequence::from_wire(
+                        icmp_packet.get_sequence_number(),
+                        zero_based,
+                    )?);
             }
             icmp::IcmpTypes::EchoRequest => return Err(SurgeError::EchoRequestPacket),
             _ => {
                 let icmp_payload = icmp_packet.payload();
-
-                if icmp_payload.len() < 32 {
-                    return Err(SurgeError::from(MalformedPacketError::PayloadTooShort {
-                        got: icmp_payload.len(),
-                        want: 32,
-                    }));
-                }
-
-                // icmp unused(4) + ip header(20) + echo icmp(4)
-                let real_ip_packet = ipv4::Ipv4Packet::new(&icmp_payload[4..])
-                    .ok_or_else(|| SurgeError::from(MalformedPacketError::NotIpv4Packet))?;
-                let identifier = u16::from_be_bytes(icmp_payload[28..30].try_into().unwrap());
-                let sequence = u16::from_be_bytes(icmp_payload[30..32].try_into().unwrap());
+                let (real_dest, identifier, sequence) = decode_original_echo(icmp_payload)?;
 
                 packet
                     .source(src_addr)
@@ -289,15 +568,20 @@ impl Icmpv4Packet {
                     .icmp_type(icmp_packet.get_icmp_type())
                     .icmp_code(icmp_packet.get_icmp_code())
                     .size(icmp_packet.packet_size())
-                    .real_dest(real_ip_packet.get_destination())
+                    .real_dest(real_dest)
                     .identifier(identifier.into())
-                    .sequence(
-                        NonZeroU16::new(sequence)
-                            .ok_or_else(|| SurgeError::UnsupportedSeqNum)?
-                            .into(),
-                    );
+                    .sequence(PingSequence::from_wire(sequence, zero_based)?);
+
+                if icmp_packet.get_icmp_type() == icmp::IcmpTypes::DestinationUnreachable
+                    && icmp_packet.get_icmp_code()
+                        == destination_unreachable::IcmpCodes::FragmentationRequiredAndDFFlagSet
+                {
+                    // icmp unused(2) + next-hop mtu(2)
+                    packet.next_hop_mtu(u16::from_be_bytes(icmp_payload[2..4].try_into().unwrap()));
+                }
             }
         }
+        packet.raw(icmp_packet.packet());
 
         Ok(packet)
     }
@@ -306,7 +590,7 @@ impl Icmpv4Packet {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::Icmpv4Packet;
+    use crate::{IcmpPacket, Icmpv4Packet};
 
     #[test]
     fn malformed_packet() {
@@ -355,21 +639,158 @@ mod tests {
     #[test]
     fn standard_packet() {
         let decoded_ipv4 = hex::decode("45000054000000007901067e8efab00e0a00f22203004176a1ee0001613dd762000000002127040000000000101112131415161718191a1b1c1d1e1f202122232425262728292a2b2c2d2e2f3031323334353637").unwrap();
-        Icmpv4Packet::decode(
+        let packet = Icmpv4Packet::decode(
             &decoded_ipv4,
             SockType::RAW,
             ("172.217.14.110").parse().unwrap(),
             ("10.0.242.34").parse().unwrap(),
         )
         .unwrap();
+        assert_eq!(packet.raw_bytes(), &decoded_ipv4[20..]);
 
         let decoded_icmp = hex::decode("03004176a1ee0001613dd762000000002127040000000000101112131415161718191a1b1c1d1e1f202122232425262728292a2b2c2d2e2f3031323334353637").unwrap();
-        Icmpv4Packet::decode(
+        let packet = Icmpv4Packet::decode(
             &decoded_icmp,
             SockType::DGRAM,
             ("172.217.14.110").parse().unwrap(),
             ("10.0.242.34").parse().unwrap(),
         )
         .unwrap();
+        assert_eq!(packet.raw_bytes(), &decoded_icmp[..]);
+    }
+
+    #[test]
+    fn checksum() {
+        // echo reply with a correct checksum
+        let mut decoded_ipv4 = hex::decode("45000054000000007901067e8efab00e0a00f22200004176a1ee0001613dd762000000002127040000000000101112131415161718191a1b1c1d1e1f202122232425262728292a2b2c2d2e2f3031323334353637").unwrap();
+        assert!(checksum_ok(&decoded_ipv4, SockType::RAW));
+        assert!(checksum_ok(&decoded_ipv4[20..], SockType::DGRAM));
+        decoded_ipv4[40] ^= 0xff;
+        assert!(!checksum_ok(&decoded_ipv4, SockType::RAW));
+    }
+
+    #[test]
+    fn ip_options() {
+        // Time exceeded in a 24 bytes IPv4 header, quoting a 24 bytes header: both carry
+        // one 4 bytes option (4 NOPs).
+        let decoded_ipv4 = hex::decode(concat!(
+            "460000400000000040010000c0000201c000020201010101",
+            "0b00000000000000",
+            "460000240000000001010000c0000202c633640701010101",
+            "0800000012340007",
+        ))
+        .unwrap();
+        let packet = Icmpv4Packet::decode(
+            &decoded_ipv4,
+            SockType::RAW,
+            ("192.0.2.1").parse().unwrap(),
+            ("192.0.2.2").parse().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(packet.get_ttl(), Some(64));
+        assert_eq!(packet.get_real_dest(), Ipv4Addr::new(198, 51, 100, 7));
+        assert_eq!(packet.get_identifier(), PingIdentifier(0x1234));
+        assert_eq!(packet.get_sequence().into_u16(), 7);
+        assert_eq!(packet.raw_bytes(), &decoded_ipv4[24..]);
+    }
+
+    #[test]
+    fn zero_based_sequence() {
+        // Echo reply with sequence number 0.
+        let decoded_icmp = hex::decode("0000000012340000aaaa").unwrap();
+        let (src_addr, dst_addr) = ("10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap());
+        assert!(matches!(
+            Icmpv4Packet::decode(&decoded_icmp, SockType::DGRAM, src_addr, dst_addr),
+            Err(SurgeError::UnsupportedSeqNum)
+        ));
+        let packet =
+            Icmpv4Packet::decode_with(&decoded_icmp, SockType::DGRAM, src_addr, dst_addr, true)
+                .unwrap();
+        assert_eq!(packet.get_sequence().into_u16(), 1);
+        assert_eq!(PingSequence(NonZeroU16::MIN).to_wire(true), 0);
+    }
+
+    #[test]
+    fn recorded_route() {
+        // NOP, then a Record Route option with two of its nine slots filled.
+        let mut options = vec![1, 7, 39, 12, 192, 0, 2, 2, 192, 0, 2, 1];
+        options.resize(40, 0);
+        assert_eq!(
+            decode_recorded_route(&options),
+            vec![Ipv4Addr::new(192, 0, 2, 2), Ipv4Addr::new(192, 0, 2, 1)]
+        );
+        // Cut short by the end of the header.
+        assert_eq!(
+            decode_recorded_route(&options[..10]),
+            vec![Ipv4Addr::new(192, 0, 2, 2)]
+        );
+        assert!(decode_recorded_route(&[7, 1]).is_empty());
+        assert_eq!(record_route_option()[..3], [7, 39, 4]);
+    }
+
+    #[test]
+    fn fragmentation_needed_packet() {
+        // type 3 code 4, next-hop mtu 1400, then the original IPv4 header and echo request.
+        let decoded_icmp =
+            hex::decode("030400000000057845000054000040004001000008080808080808080800000000630007")
+                .unwrap();
+        let packet = Icmpv4Packet::decode(
+            &decoded_icmp,
+            SockType::DGRAM,
+            ("10.0.0.1").parse().unwrap(),
+            ("10.0.242.34").parse().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(packet.get_next_hop_mtu(), Some(1400));
+        assert_eq!(packet.get_real_dest(), Ipv4Addr::new(8, 8, 8, 8));
+        assert_eq!(packet.get_sequence().into_u16(), 7);
+    }
+
+    #[test]
+    fn host_unreachable_packet() {
+        // type 3 code 1, then the original IPv4 header and echo request.
+        let decoded_icmp =
+            hex::decode("030100000000000045000054000040004001000008080808080808080800000000630007")
+                .unwrap();
+        let packet = Icmpv4Packet::decode(
+            &decoded_icmp,
+            SockType::DGRAM,
+            ("10.0.0.1").parse().unwrap(),
+            ("10.0.242.34").parse().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(packet.get_real_dest(), Ipv4Addr::new(8, 8, 8, 8));
+        assert!(matches!(
+            IcmpPacket::V4(packet).to_error(),
+            Some(SurgeError::Unreachable { code: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn timestamp_reply_packet() {
+        // ident 77, seq 3, originate 100, receive 200, transmit 201.
+        let decoded_ipv4 = hex::decode(
+            "45000028000000004001000008080808c0a800010e000000004d0003000000640000\
+             00c8000000c9",
+        )
+        .unwrap();
+        let packet = Icmpv4Packet::decode(
+            &decoded_ipv4,
+            SockType::RAW,
+            ("8.8.8.8").parse().unwrap(),
+            ("192.168.0.1").parse().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(packet.get_ip_id(), Some(0));
+        assert_eq!(packet.get_identifier(), PingIdentifier(77));
+        assert_eq!(packet.get_sequence().into_u16(), 3);
+        assert_eq!(
+            packet.get_timestamps(),
+            Some(IcmpTimestamps {
+                originate: 100,
+                receive: 200,
+                transmit: 201,
+            })
+        );
     }
 }
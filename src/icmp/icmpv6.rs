@@ -1,47 +1,75 @@
 use std::convert::TryInto;
 use std::net::Ipv6Addr;
 use std::num::NonZeroU16;
+use std::ops::Range;
 
 use pnet_packet::icmpv6::{self, Icmpv6Code, Icmpv6Type};
+use pnet_packet::ipv6;
 use pnet_packet::Packet;
 use pnet_packet::PacketSize;
 
 use crate::error::{MalformedPacketError, Result, SurgeError};
 
-use super::{PingIdentifier, PingSequence};
+use super::{extensions::IcmpExtensions, PingIdentifier, PingSequence, ICMP_HEADER_LEN};
 
-#[allow(dead_code)]
-pub fn make_icmpv6_echo_packet(
+/// Build an echo request into `buf`, zeroed and [`ICMP_HEADER_LEN`] bytes longer than
+/// `payload`.
+pub(crate) fn write_icmpv6_echo_packet(
+    buf: &mut [u8],
     ident: PingIdentifier,
-    seq_cnt: PingSequence,
+    seq_cnt: u16,
     payload: &[u8],
-) -> Result<Vec<u8>> {
-    let mut buf = vec![0; 8 + payload.len()]; // 8 bytes of header, then payload
-    let mut packet = icmpv6::echo_request::MutableEchoRequestPacket::new(&mut buf[..])
+) -> Result<()> {
+    let mut packet = icmpv6::echo_request::MutableEchoRequestPacket::new(buf)
         .ok_or(SurgeError::IncorrectBufferSize)?;
     packet.set_icmpv6_type(icmpv6::Icmpv6Types::EchoRequest);
     packet.set_identifier(ident.into_u16());
-    packet.set_sequence_number(seq_cnt.into_u16());
+    packet.set_sequence_number(seq_cnt);
     packet.set_payload(payload);
 
     // Per https://tools.ietf.org/html/rfc3542#section-3.1 the checksum is
-    // omitted, the kernel will insert it.
+    // omitted, the kernel will insert it, see `set_checksum` otherwise.
 
-    Ok(packet.packet().to_vec())
+    Ok(())
+}
+
+/// Fill in the checksum of an ICMPv6 message sent from `src_addr` to `dst_addr`, for
+/// kernels which leave it to the caller, see `Config::builder().icmpv6_checksum()`.
+pub(crate) fn set_checksum(buf: &mut [u8], src_addr: Ipv6Addr, dst_addr: Ipv6Addr) -> Result<()> {
+    let mut packet =
+        icmpv6::MutableIcmpv6Packet::new(buf).ok_or(SurgeError::IncorrectBufferSize)?;
+    packet.set_checksum(0);
+    let checksum = icmpv6::checksum(&packet.to_immutable(), &src_addr, &dst_addr);
+    packet.set_checksum(checksum);
+    Ok(())
+}
+
+/// Whether the checksum of a received ICMPv6 message is correct, it covers a pseudo-header
+/// made of the source and destination addresses.
+pub(crate) fn checksum_ok(buf: &[u8], src_addr: Ipv6Addr, dst_addr: Ipv6Addr) -> bool {
+    match icmpv6::Icmpv6Packet::new(buf) {
+        Some(packet) => icmpv6::checksum(&packet, &src_addr, &dst_addr) == packet.get_checksum(),
+        None => false,
+    }
 }
 
 /// Packet structure returned by ICMPv6.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Icmpv6Packet {
     source: Ipv6Addr,
     destination: Ipv6Addr,
     max_hop_limit: u8,
+    hop_limit: Option<u8>,
     icmpv6_type: Icmpv6Type,
     icmpv6_code: Icmpv6Code,
     size: usize,
     real_dest: Ipv6Addr,
     identifier: PingIdentifier,
     sequence: PingSequence,
+    /// The data of an echo reply, within `raw`.
+    payload: Range<usize>,
+    mtu: Option<u32>,
+    raw: Vec<u8>,
 }
 
 impl Default for Icmpv6Packet {
@@ -50,12 +78,16 @@ impl Default for Icmpv6Packet {
             source: Ipv6Addr::LOCALHOST,
             destination: Ipv6Addr::LOCALHOST,
             max_hop_limit: 0,
+            hop_limit: None,
             icmpv6_type: Icmpv6Type::new(0),
             icmpv6_code: Icmpv6Code::new(0),
             size: 0,
             real_dest: Ipv6Addr::LOCALHOST,
             identifier: PingIdentifier(0),
             sequence: PingSequence(NonZeroU16::new(1).unwrap()),
+            payload: 0..0,
+            mtu: None,
+            raw: Vec::new(),
         }
     }
 }
@@ -91,6 +123,17 @@ impl Icmpv6Packet {
         self.max_hop_limit
     }
 
+    pub(crate) fn hop_limit(&mut self, hop_limit: u8) -> &mut Self {
+        self.hop_limit = Some(hop_limit);
+        self
+    }
+
+    /// Get the hop limit of the received packet, as reported by the `IPV6_HOPLIMIT`
+    /// control message. `None` if it could not be determined.
+    pub fn get_hop_limit(&self) -> Option<u8> {
+        self.hop_limit
+    }
+
     fn icmpv6_type(&mut self, icmpv6_type: Icmpv6Type) -> &mut Self {
         self.icmpv6_type = icmpv6_type;
         self
@@ -116,7 +159,7 @@ impl Icmpv6Packet {
         self
     }
 
-    /// Get the size of the icmp_v6 packet.
+    /// Get the size of the icmp_v6 packet, header included, see [`echo_payload_len`](super::echo_payload_len).
     pub fn get_size(&self) -> usize {
         self.size
     }
@@ -137,7 +180,9 @@ impl Icmpv6Packet {
         self
     }
 
-    /// Get the identifier of the icmp_v6 packet.
+    /// Get the identifier of the icmp_v6 packet: of the echo reply, or of the request quoted
+    /// by an error message. On Linux ICMP sockets, the one the kernel assigned, which is
+    /// the port of the socket.
     pub fn get_identifier(&self) -> PingIdentifier {
         self.identifier
     }
@@ -152,8 +197,55 @@ impl Icmpv6Packet {
         self.sequence
     }
 
+    fn payload(&mut self, payload: Range<usize>) -> &mut Self {
+        self.payload = payload;
+        self
+    }
+
+    /// Get the data echoed back by an echo reply, empty for other messages.
+    pub fn get_payload(&self) -> &[u8] {
+        &self.raw[self.payload.clone()]
+    }
+
+    fn mtu(&mut self, mtu: u32) -> &mut Self {
+        self.mtu = Some(mtu);
+        self
+    }
+
+    /// For a Packet Too Big message, the MTU of the next-hop link as advertised by the router.
+    pub fn get_mtu(&self) -> Option<u32> {
+        self.mtu
+    }
+
+    fn raw(&mut self, raw: &[u8]) -> &mut Self {
+        self.raw = raw.to_vec();
+        self
+    }
+
+    /// Get the whole ICMP message as received, header included, to parse what the typed
+    /// accessors do not cover, such as RFC 4884 extensions.
+    pub fn raw_bytes(&self) -> &[u8] {
+        &self.raw
+    }
+
+    /// The RFC 4884 extensions of a Time Exceeded or Destination Unreachable message, see
+    /// [`IcmpExtensions::parse_v6`].
+    pub fn extensions(&self) -> Option<IcmpExtensions> {
+        match self.icmpv6_type {
+            icmpv6::Icmpv6Types::TimeExceeded | icmpv6::Icmpv6Types::DestinationUnreachable => {
+                IcmpExtensions::parse_v6(&self.raw)
+            }
+            _ => None,
+        }
+    }
+
     /// Decode into icmpv6 packet from the socket message.
     pub fn decode(buf: &[u8], destination: Ipv6Addr) -> Result<Self> {
+        Self::decode_with(buf, destination, false)
+    }
+
+    /// [`decode`](Self::decode), see `ConfigBuilder::zero_based_sequence` for `zero_based`.
+    pub(crate) fn decode_with(buf: &[u8], destination: Ipv6Addr, zero_based: bool) -> Result<Self> {
         // The IPv6 header is automatically cropped off when recvfrom() is used.
         let icmpv6_packet = icmpv6::Icmpv6Packet::new(buf)
             .ok_or_else(|| SurgeError::from(MalformedPacketError::NotIcmpv6Packet))?;
@@ -177,25 +269,28 @@ impl Icmpv6Packet {
                     .icmpv6_type(icmpv6_packet.get_icmpv6_type())
                     .icmpv6_code(icmpv6_packet.get_icmpv6_code())
                     .size(icmpv6_packet.packet().len())
+                    .payload(ICMP_HEADER_LEN..buf.len())
                     .real_dest(destination)
                     .identifier(identifier.into())
-                    .sequence(
-                        NonZeroU16::new(sequence)
-                            .ok_or_else(|| SurgeError::UnsupportedSeqNum)?
-                            .into(),
-                    );
+                    .sequence(PingSequence::from_wire(sequence, zero_based)?)
+                    .raw(buf);
                 Ok(packet)
             }
-            _ => {
-                // ipv6 header(40) + icmpv6 echo header(4)
-                if icmpv6_payload.len() < 48 {
+            icmpv6::Icmpv6Types::DestinationUnreachable
+            | icmpv6::Icmpv6Types::PacketTooBig
+            | icmpv6::Icmpv6Types::TimeExceeded
+            | icmpv6::Icmpv6Types::ParameterProblem => {
+                // icmpv6 unused(4) + ipv6 header(40) + echo icmpv6(8)
+                if icmpv6_payload.len() < 52 {
                     return Err(SurgeError::from(MalformedPacketError::PayloadTooShort {
                         got: icmpv6_payload.len(),
-                        want: 48,
+                        want: 52,
                     }));
                 }
-                let identifier = u16::from_be_bytes(icmpv6_payload[44..46].try_into().unwrap());
-                let sequence = u16::from_be_bytes(icmpv6_payload[46..48].try_into().unwrap());
+                let real_ip_packet = ipv6::Ipv6Packet::new(&icmpv6_payload[4..])
+                    .ok_or_else(|| SurgeError::from(MalformedPacketError::NotIpv6Packet))?;
+                let identifier = u16::from_be_bytes(icmpv6_payload[48..50].try_into().unwrap());
+                let sequence = u16::from_be_bytes(icmpv6_payload[50..52].try_into().unwrap());
                 let mut packet = Icmpv6Packet::default();
                 packet
                     .source(destination)
@@ -204,14 +299,74 @@ impl Icmpv6Packet {
                     .icmpv6_type(icmpv6_packet.get_icmpv6_type())
                     .icmpv6_code(icmpv6_packet.get_icmpv6_code())
                     .size(icmpv6_packet.packet_size())
+                    .real_dest(real_ip_packet.get_destination())
                     .identifier(identifier.into())
-                    .sequence(
-                        NonZeroU16::new(sequence)
-                            .ok_or_else(|| SurgeError::UnsupportedSeqNum)?
-                            .into(),
-                    );
+                    .sequence(PingSequence::from_wire(sequence, zero_based)?)
+                    .raw(buf);
+                if icmpv6_packet.get_icmpv6_type() == icmpv6::Icmpv6Types::PacketTooBig {
+                    // mtu(4) in place of the unused field
+                    packet.mtu(u32::from_be_bytes(icmpv6_payload[0..4].try_into().unwrap()));
+                }
                 Ok(packet)
             }
+            // Neighbor discovery, router advertisements, MLD...
+            _ => Err(SurgeError::NotPingRelated),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packet_too_big() {
+        // type 2 code 0, mtu 1280, then the original IPv6 header and echo request.
+        let decoded = hex::decode(concat!(
+            "0200000000000500",
+            "6000000000403a40",
+            "20010db8000000000000000000000002",
+            "20010db8000000000000000000000001",
+            "8000000012340007",
+        ))
+        .unwrap();
+        let packet = Icmpv6Packet::decode(&decoded, "2001:db8::fe".parse().unwrap()).unwrap();
+        assert_eq!(packet.get_mtu(), Some(1280));
+        assert_eq!(
+            packet.get_real_dest(),
+            "2001:db8::1".parse::<Ipv6Addr>().unwrap()
+        );
+        assert_eq!(packet.get_identifier(), PingIdentifier(0x1234));
+        assert_eq!(packet.get_sequence().into_u16(), 7);
+        assert!(matches!(
+            crate::IcmpPacket::V6(packet).to_error(),
+            Some(SurgeError::PacketTooBig { mtu: 1280, .. })
+        ));
+    }
+
+    #[test]
+    fn neighbor_advertisement() {
+        // type 136 code 0, flags, then the target address.
+        let decoded = hex::decode(concat!(
+            "8800000060000000",
+            "20010db8000000000000000000000002",
+        ))
+        .unwrap();
+        assert!(matches!(
+            Icmpv6Packet::decode(&decoded, "2001:db8::2".parse().unwrap()),
+            Err(SurgeError::NotPingRelated)
+        ));
+    }
+
+    #[test]
+    fn manual_checksum() {
+        let src = "2001:db8::2".parse().unwrap();
+        let dst = "2001:db8::1".parse().unwrap();
+        let mut packet = vec![0; crate::echo_size(4)];
+        write_icmpv6_echo_packet(&mut packet, PingIdentifier(0x1234), 7, b"ping").unwrap();
+        assert!(!checksum_ok(&packet, src, dst));
+        set_checksum(&mut packet, src, dst).unwrap();
+        assert!(checksum_ok(&packet, src, dst));
+        assert!(!checksum_ok(&packet, "2001:db8::3".parse().unwrap(), dst));
+    }
+}
@@ -1,11 +1,34 @@
+use std::convert::TryFrom;
 use std::fmt;
+use std::net::IpAddr;
 use std::num::NonZeroU16;
 
+use pnet_packet::{icmp::IcmpTypes, icmpv6::Icmpv6Types};
+
+use crate::error::SurgeError;
+
+pub mod extensions;
 pub mod icmpv4;
 pub mod icmpv6;
 
+/// Length of the header of echo messages: type, code, checksum, identifier and sequence
+/// number. The payload follows.
+pub const ICMP_HEADER_LEN: usize = 8;
+
+/// The size of an echo message with `payload_len` bytes of payload, header included: the
+/// size `ping` reports replies with, 64 bytes for its default 56 data bytes.
+pub const fn echo_size(payload_len: usize) -> usize {
+    ICMP_HEADER_LEN + payload_len
+}
+
+/// The payload length of an echo message of `size` bytes, header included, `None` if it is
+/// shorter than the header. The inverse of [`echo_size`].
+pub const fn echo_payload_len(size: usize) -> Option<usize> {
+    size.checked_sub(ICMP_HEADER_LEN)
+}
+
 /// Represents the ICMP reply packet.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum IcmpPacket {
     /// An ICMPv4 packet abstraction.
     V4(icmpv4::Icmpv4Packet),
@@ -14,6 +37,7 @@ pub enum IcmpPacket {
 }
 
 impl IcmpPacket {
+    /// The identifier the reply carried, see [`Icmpv4Packet::get_identifier`](icmpv4::Icmpv4Packet::get_identifier).
     pub fn get_identifier(&self) -> PingIdentifier {
         match self {
             IcmpPacket::V4(packet) => packet.get_identifier(),
@@ -27,6 +51,124 @@ impl IcmpPacket {
             IcmpPacket::V6(packet) => packet.get_sequence(),
         }
     }
+
+    /// Get the raw ICMP (or ICMPv6) message type, e.g. 0 for an ICMPv4 Echo Reply
+    /// or 11 for Time Exceeded.
+    pub fn get_icmp_type(&self) -> u8 {
+        match self {
+            IcmpPacket::V4(packet) => packet.get_icmp_type().0,
+            IcmpPacket::V6(packet) => packet.get_icmpv6_type().0,
+        }
+    }
+
+    /// Get the raw ICMP (or ICMPv6) message code.
+    pub fn get_icmp_code(&self) -> u8 {
+        match self {
+            IcmpPacket::V4(packet) => packet.get_icmp_code().0,
+            IcmpPacket::V6(packet) => packet.get_icmpv6_code().0,
+        }
+    }
+
+    /// Get the data carried by an echo reply, empty for other messages.
+    pub fn get_payload(&self) -> &[u8] {
+        match self {
+            IcmpPacket::V4(packet) => packet.get_payload(),
+            IcmpPacket::V6(packet) => packet.get_payload(),
+        }
+    }
+
+    /// Get the whole ICMP message as received, see [`Icmpv4Packet::raw_bytes`](icmpv4::Icmpv4Packet::raw_bytes).
+    pub fn raw_bytes(&self) -> &[u8] {
+        match self {
+            IcmpPacket::V4(packet) => packet.raw_bytes(),
+            IcmpPacket::V6(packet) => packet.raw_bytes(),
+        }
+    }
+
+    /// Address of the host which sent this packet, a router for ICMP error messages.
+    pub fn get_source(&self) -> IpAddr {
+        match self {
+            IcmpPacket::V4(packet) => packet.get_source().into(),
+            IcmpPacket::V6(packet) => packet.get_source().into(),
+        }
+    }
+
+    /// The IPv4 TTL or IPv6 hop limit of the packet, when it could be determined.
+    pub fn get_ttl(&self) -> Option<u8> {
+        match self {
+            IcmpPacket::V4(packet) => packet.get_ttl(),
+            IcmpPacket::V6(packet) => packet.get_hop_limit(),
+        }
+    }
+
+    /// A plain, serializable view of the main fields of the packet.
+    pub fn summary(&self) -> IcmpPacketSummary {
+        IcmpPacketSummary {
+            icmp_type: self.get_icmp_type(),
+            icmp_code: self.get_icmp_code(),
+            source: self.get_source(),
+            sequence: self.get_sequence().into_u16(),
+            ttl: self.get_ttl(),
+        }
+    }
+
+    /// Address the original echo request was sent to.
+    pub fn get_real_dest(&self) -> IpAddr {
+        match self {
+            IcmpPacket::V4(packet) => packet.get_real_dest().into(),
+            IcmpPacket::V6(packet) => packet.get_real_dest().into(),
+        }
+    }
+
+    /// Turn an ICMP error message answering one of our echo requests into the matching error.
+    pub(crate) fn to_error(&self) -> Option<SurgeError> {
+        match self {
+            IcmpPacket::V4(packet) => match packet.get_icmp_type() {
+                IcmpTypes::TimeExceeded => Some(SurgeError::TimeExceeded {
+                    responder: self.get_source(),
+                    code: packet.get_icmp_code().0,
+                }),
+                IcmpTypes::DestinationUnreachable => Some(match packet.get_next_hop_mtu() {
+                    Some(mtu) => SurgeError::FragmentationNeeded {
+                        responder: self.get_source(),
+                        mtu,
+                    },
+                    None => SurgeError::Unreachable {
+                        responder: self.get_source(),
+                        code: packet.get_icmp_code().0,
+                    },
+                }),
+                _ => None,
+            },
+            IcmpPacket::V6(packet) => match packet.get_icmpv6_type() {
+                Icmpv6Types::TimeExceeded => Some(SurgeError::TimeExceeded {
+                    responder: self.get_source(),
+                    code: packet.get_icmpv6_code().0,
+                }),
+                Icmpv6Types::DestinationUnreachable => Some(SurgeError::Unreachable {
+                    responder: self.get_source(),
+                    code: packet.get_icmpv6_code().0,
+                }),
+                Icmpv6Types::PacketTooBig => Some(SurgeError::PacketTooBig {
+                    responder: self.get_source(),
+                    mtu: packet.get_mtu().unwrap_or(0),
+                }),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// The main fields of an [`IcmpPacket`], see [`IcmpPacket::summary`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IcmpPacketSummary {
+    pub icmp_type: u8,
+    pub icmp_code: u8,
+    pub source: IpAddr,
+    pub sequence: u16,
+    /// IPv4 TTL or IPv6 hop limit.
+    pub ttl: Option<u8>,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
@@ -57,6 +199,24 @@ impl PingSequence {
     pub fn into_u16(self) -> u16 {
         self.0.get()
     }
+
+    /// The sequence number on the wire, one less if `zero_based`, see
+    /// `ConfigBuilder::zero_based_sequence`.
+    pub(crate) fn to_wire(self, zero_based: bool) -> u16 {
+        self.into_u16() - u16::from(zero_based)
+    }
+
+    /// The sequence number of `wire` on the wire, see [`to_wire`](Self::to_wire).
+    pub(crate) fn from_wire(wire: u16, zero_based: bool) -> Result<Self, SurgeError> {
+        NonZeroU16::new(wire.wrapping_add(u16::from(zero_based)))
+            .map(Self)
+            .ok_or(SurgeError::UnsupportedSeqNum)
+    }
+
+    /// The following sequence number, wrapping from `u16::MAX` back to 1.
+    pub fn next(self) -> Self {
+        Self(self.0.checked_add(1).unwrap_or(NonZeroU16::MIN))
+    }
 }
 
 impl fmt::Display for PingSequence {
@@ -70,3 +230,37 @@ impl From<NonZeroU16> for PingSequence {
         Self(seq_cnt)
     }
 }
+
+/// Fails with [`SurgeError::UnsupportedSeqNum`] for 0.
+impl TryFrom<u16> for PingSequence {
+    type Error = SurgeError;
+
+    fn try_from(seq: u16) -> Result<Self, SurgeError> {
+        NonZeroU16::new(seq)
+            .map(Self)
+            .ok_or(SurgeError::UnsupportedSeqNum)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sequence() {
+        let last = PingSequence::try_from(u16::MAX).unwrap();
+        assert_eq!(last.next().into_u16(), 1);
+        assert_eq!(last.next().next().into_u16(), 2);
+        assert!(matches!(
+            PingSequence::try_from(0),
+            Err(SurgeError::UnsupportedSeqNum)
+        ));
+    }
+
+    #[test]
+    fn echo_sizes() {
+        assert_eq!(echo_size(56), 64);
+        assert_eq!(echo_payload_len(64), Some(56));
+        assert_eq!(echo_payload_len(4), None);
+    }
+}
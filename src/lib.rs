@@ -2,19 +2,43 @@ mod client;
 mod config;
 mod error;
 mod icmp;
+#[cfg(all(test, any(target_os = "linux", target_os = "android")))]
+mod loopback;
 mod ping;
+mod pool;
+mod rate;
+mod resolve;
+mod session;
+mod stats;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod sys;
 
 use std::num::NonZeroU16;
 use std::{net::IpAddr, time::Duration};
 
-pub use client::{AsyncSocket, Client};
-pub use config::{Config, ConfigBuilder};
-pub use error::SurgeError;
+#[doc(hidden)]
+pub use client::ReplyMapBench;
+pub use client::{
+    AsyncSocket, BlastReport, Client, ClientGroup, PingListOpts, ReplyWaiter, SocketMode,
+    SupportedModes,
+};
+pub use config::{Config, ConfigBuilder, Icmpv6Checksum, IdentifierStrategy, MatchPolicy};
+pub use error::{MalformedPacketError, SurgeError};
 pub use icmp::{
-    icmpv4::Icmpv4Packet, icmpv6::Icmpv6Packet, IcmpPacket, PingIdentifier, PingSequence,
+    echo_payload_len, echo_size,
+    extensions::{
+        IcmpExtensionObject, IcmpExtensions, InterfaceInformation, InterfaceRole, MplsLabel,
+    },
+    icmpv4::{IcmpTimestamps, Icmpv4Packet, RECORD_ROUTE_MAX_HOPS},
+    icmpv6::Icmpv6Packet,
+    IcmpPacket, IcmpPacketSummary, PingIdentifier, PingSequence, ICMP_HEADER_LEN,
 };
-pub use ping::Pinger;
+pub use ipnet::IpNet;
+pub use ping::{PingOutcome, PingStream, Pinger, ProbeKind, Sweep};
 use rand::random;
+pub use resolve::{resolve, Resolver, TokioResolver};
+pub use session::PingSession;
+pub use stats::{PingStats, PingSummary, Stats};
 
 #[derive(Debug, Default, Clone, Copy)]
 pub enum ICMP {
@@ -0,0 +1,145 @@
+//! Tests of the whole send, receive and correlate path with real ICMP sockets, the kernel
+//! answering the echo requests sent to the loopback address. They pass without doing
+//! anything where the process may not open ICMP sockets, or the kernel ignores echo
+//! requests.
+
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    num::NonZeroU16,
+    time::Duration,
+};
+
+use pnet_packet::util;
+use socket2::Type as SockType;
+use tokio::{task, time};
+
+use crate::{
+    client::AsyncSocket, Client, Config, IcmpPacket, PingIdentifier, PingSequence, Pinger,
+    SurgeError,
+};
+
+const LOOPBACK: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+fn seq(seq: u16) -> PingSequence {
+    PingSequence(NonZeroU16::new(seq).unwrap())
+}
+
+/// A client with a datagram ICMP socket, or a raw one if only that is allowed, and its
+/// pinger of the loopback address. `None` if neither socket is allowed or the kernel does
+/// not answer a first request. Tests run concurrently, and raw sockets receive every reply:
+/// each test takes its own `ident`.
+async fn loopback_pinger(ident: u16) -> Option<(Client, Pinger)> {
+    let config = Config::builder().sock_type_hint(SockType::DGRAM).build();
+    let client = Client::new(&config).ok()?;
+    let pinger = client.pinger(LOOPBACK, PingIdentifier(ident)).await;
+    pinger
+        .ping_timeout(seq(1), &[], Duration::from_secs(1))
+        .await
+        .ok()?;
+    Some((client, pinger))
+}
+
+/// Answer every echo request sent to the loopback address a second time, through a raw
+/// socket. `None` if the process may not open one.
+fn spawn_echo_responder() -> Option<task::JoinHandle<()>> {
+    let config = Config::builder().sock_type_hint(SockType::RAW).build();
+    let socket = AsyncSocket::new(&config).ok()?;
+    if socket.get_type() != SockType::RAW {
+        return None;
+    }
+    Some(task::spawn(async move {
+        let mut buf = [0; 2048];
+        let target = SocketAddr::new(LOOPBACK, 0);
+        while let Ok((len, _)) = socket.recv_from(&mut buf).await {
+            // Raw IPv4 sockets deliver the IP header.
+            let header_len = usize::from(buf[0] & 0x0f) * 4;
+            let message = &mut buf[header_len..len];
+            if message.len() < 8 || message[0] != 8 {
+                continue;
+            }
+            message[0] = 0;
+            message[2..4].copy_from_slice(&[0, 0]);
+            let checksum = util::checksum(message, 1);
+            message[2..4].copy_from_slice(&checksum.to_be_bytes());
+            let _ = socket.send_to(message, &target).await;
+        }
+    }))
+}
+
+#[tokio::test]
+async fn round_trip() {
+    let Some((client, pinger)) = loopback_pinger(1).await else {
+        return;
+    };
+    for n in 2..=4 {
+        let (packet, rtt) = pinger
+            .ping_timeout(seq(n), b"loopback", Duration::from_secs(1))
+            .await
+            .unwrap();
+        let IcmpPacket::V4(packet) = packet else {
+            panic!("not an ICMPv4 reply");
+        };
+        assert_eq!(packet.get_sequence(), seq(n));
+        assert_eq!(packet.get_payload(), b"loopback");
+        assert!(rtt < Duration::from_secs(1));
+    }
+    assert_eq!(client.pending_count(), 0);
+}
+
+#[tokio::test]
+async fn identical_requests() {
+    let Some((_client, pinger)) = loopback_pinger(2).await else {
+        return;
+    };
+    let _waiter = pinger.ping_send(seq(2), &[]).await.unwrap();
+    assert!(matches!(
+        pinger.ping_send(seq(2), &[]).await,
+        Err(SurgeError::IdenticalRequests { .. })
+    ));
+}
+
+// Single threaded, so that the reply cannot be delivered before the request is abandoned.
+#[tokio::test]
+async fn late_reply() {
+    let Some((client, pinger)) = loopback_pinger(3).await else {
+        return;
+    };
+    let (send_time, waiter) = pinger.ping_send(seq(2), &[]).await.unwrap();
+    pinger.reset();
+    assert!(matches!(
+        pinger.ping_recv(send_time, waiter).await,
+        Err(SurgeError::Timeout { .. })
+    ));
+    assert_eq!(client.pending_count(), 0);
+    // The reply still arrives, for nobody.
+    time::timeout(Duration::from_secs(1), async {
+        while client.unmatched_replies() == 0 {
+            time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn duplicate_reply() {
+    let Some((client, pinger)) = loopback_pinger(4).await else {
+        return;
+    };
+    let Some(responder) = spawn_echo_responder() else {
+        return;
+    };
+    pinger
+        .ping_timeout(seq(2), &[], Duration::from_secs(1))
+        .await
+        .unwrap();
+    // Answered by both the kernel and the responder.
+    time::timeout(Duration::from_secs(1), async {
+        while client.duplicate_replies() == 0 {
+            time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+    responder.abort();
+}
@@ -1,92 +1,337 @@
+use std::collections::{hash_map::Entry, HashMap};
 use std::error::Error;
+use std::fmt::Write;
+use std::fs;
+use std::io::{self, Write as _};
 use std::iter::FromIterator;
 use std::net::{IpAddr, SocketAddr};
 use std::num::NonZeroU16;
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use clap::Parser;
 use futures::future::pending;
 use futures::stream::FuturesUnordered;
 use futures::{FutureExt, StreamExt};
+use parking_lot::Mutex;
 use rand::random;
-use surge_ping::{Client, Config, IcmpPacket, PingIdentifier, PingSequence, ICMP};
+use serde_json::json;
+use surge_ping::{
+    Client, Config, IcmpPacket, PingIdentifier, PingSequence, Resolver, Stats, TokioResolver, ICMP,
+};
+use tokio::signal;
 use tokio::sync::mpsc;
-use tokio::time;
+use tokio::{task, time};
 
 #[derive(Default, Debug)]
 struct Answer {
     host: String,
+    /// Echo requests sent, including the ones still waiting for their reply.
     transmitted: usize,
-    received: usize,
-    durations: Vec<Duration>,
+    /// In constant memory, for pinging until interrupted.
+    stats: Stats,
+    /// Results still to be left out of the round-trip time figures, see `--warmup`.
+    warmup: usize,
+}
+
+/// In milliseconds, 0 when there is no round-trip time to derive it from.
+fn millis(dur: Option<Duration>) -> f64 {
+    dur.map(|dur| dur.as_secs_f64() * 1000.0)
+        .unwrap_or_default()
 }
 
 impl Answer {
-    fn new(host: String, transmitted: usize) -> Answer {
+    fn new(host: String) -> Answer {
         Answer {
             host,
-            transmitted,
-            received: 0,
-            durations: Vec::new(),
+            ..Answer::default()
+        }
+    }
+
+    fn received(&self) -> u64 {
+        self.stats.received()
+    }
+
+    /// Packet loss in percent.
+    fn loss(&self) -> f64 {
+        if self.transmitted == 0 {
+            return 0.0;
         }
+        100.0 * (1.0 - (self.received() as f64 / self.transmitted as f64))
     }
 
     fn update(&mut self, dur: Option<Duration>) {
-        if let Some(dur) = dur {
-            self.received += 1;
-            self.durations.push(dur);
+        if self.warmup > 0 {
+            self.warmup -= 1;
+            self.stats.record_warmup(dur);
+        } else if let Some(dur) = dur {
+            self.stats.record(dur);
+        } else {
+            self.stats.record_loss();
         }
     }
 
     fn min(&self) -> f64 {
-        self.durations
-            .iter()
-            .min()
-            .map(|dur| dur.as_secs_f64() * 1000.0)
-            .unwrap_or_default()
+        millis(self.stats.min())
     }
 
     fn max(&self) -> f64 {
-        self.durations
-            .iter()
-            .max()
-            .map(|dur| dur.as_secs_f64() * 1000.0)
-            .unwrap_or_default()
+        millis(self.stats.max())
     }
 
     fn avg(&self) -> f64 {
-        let sum: Duration = self.durations.iter().sum();
-        sum.checked_div(self.durations.len() as u32)
-            .map(|dur| dur.as_secs_f64() * 1000.0)
-            .unwrap_or_default()
+        millis(self.stats.mean())
+    }
+
+    /// Mean absolute difference between consecutive round-trip times, in milliseconds.
+    fn jitter(&self) -> f64 {
+        millis(self.stats.jitter())
     }
 
+    /// Population standard deviation, in milliseconds.
     fn stddev(&self) -> f64 {
-        let sum = self
-            .durations
-            .iter()
-            .map(|dur| dur.as_secs_f64().powi(2) * 1e6)
-            .sum::<f64>();
-        (sum / self.durations.len() as f64 - self.avg().powi(2)).sqrt()
+        millis(self.stats.stddev())
+    }
+
+    /// The round-trip time below which `percentile` percent of them fall, approximately,
+    /// in milliseconds.
+    fn percentile(&self, percentile: f64) -> f64 {
+        millis(self.stats.percentile(percentile))
     }
 
-    fn output(&self) {
+    fn output(&self, percentiles: bool) {
         println!("\n--- {} ping statistics ---", self.host);
         println!(
             "{} packets transmitted, {} packets received, {:.2}% packet loss",
             self.transmitted,
-            self.received,
-            100.0 * (1.0 - (self.received as f64 / self.transmitted as f64)),
+            self.received(),
+            self.loss(),
         );
-        if self.received > 1 {
+        if self.received() > 1 {
             let (min, avg, max, stddev) = (self.min(), self.avg(), self.max(), self.stddev());
             println!("round-trip min/avg/max/stddev = {min:.3}/{avg:.3}/{max:.3}/{stddev:.3} ms");
+            println!("jitter = {:.3} ms", self.jitter());
+        }
+        if percentiles && self.received() > 0 {
+            let [p50, p90, p95, p99] = PERCENTILES.map(|percentile| self.percentile(percentile));
+            println!("round-trip p50/p90/p95/p99 = {p50:.3}/{p90:.3}/{p95:.3}/{p99:.3} ms");
+        }
+        if self.stats.longest_loss_run() > 1 {
+            println!(
+                "longest loss burst = {} packets",
+                self.stats.longest_loss_run()
+            );
+        }
+    }
+
+    /// The summary as a JSON object, round-trip times in milliseconds.
+    fn output_json(&self, percentiles: bool) {
+        let mut summary = json!({
+            "host": self.host,
+            "transmitted": self.transmitted,
+            "received": self.received(),
+            "loss": self.loss() / 100.0,
+        });
+        if self.received() > 0 {
+            summary["min"] = self.min().into();
+            summary["avg"] = self.avg().into();
+            summary["max"] = self.max().into();
+            summary["stddev"] = self.stddev().into();
+            summary["jitter"] = self.jitter().into();
+            if percentiles {
+                for percentile in PERCENTILES {
+                    summary[format!("p{percentile}")] = self.percentile(percentile).into();
+                }
+            }
         }
+        summary["longest_loss_run"] = self.stats.longest_loss_run().into();
+        println!("{summary}");
+    }
+
+    /// The metrics in the Prometheus text exposition format.
+    fn prometheus(&self) -> String {
+        let host = self.host.replace('\\', "\\\\").replace('"', "\\\"");
+        let rtt_sum = self.stats.sum();
+        let mut text = String::new();
+        let _ = writeln!(
+            text,
+            "# HELP ping_rtt_seconds Round trip time of the replies."
+        );
+        let _ = writeln!(text, "# TYPE ping_rtt_seconds summary");
+        let _ = writeln!(
+            text,
+            "ping_rtt_seconds_sum{{host=\"{host}\"}} {}",
+            rtt_sum.as_secs_f64()
+        );
+        let _ = writeln!(
+            text,
+            "ping_rtt_seconds_count{{host=\"{host}\"}} {}",
+            self.stats.count()
+        );
+        let _ = writeln!(text, "# HELP ping_packets_sent_total Echo requests sent.");
+        let _ = writeln!(text, "# TYPE ping_packets_sent_total counter");
+        let _ = writeln!(
+            text,
+            "ping_packets_sent_total{{host=\"{host}\"}} {}",
+            self.transmitted
+        );
+        let _ = writeln!(
+            text,
+            "# HELP ping_packets_received_total Echo replies received."
+        );
+        let _ = writeln!(text, "# TYPE ping_packets_received_total counter");
+        let _ = writeln!(
+            text,
+            "ping_packets_received_total{{host=\"{host}\"}} {}",
+            self.received()
+        );
+        let _ = writeln!(
+            text,
+            "# HELP ping_packet_loss_ratio Fraction of requests unanswered."
+        );
+        let _ = writeln!(text, "# TYPE ping_packet_loss_ratio gauge");
+        let _ = writeln!(
+            text,
+            "ping_packet_loss_ratio{{host=\"{host}\"}} {}",
+            self.loss() / 100.0
+        );
+        text
+    }
+
+    /// Write the metrics to `path` for node_exporter's textfile collector. The file is
+    /// replaced atomically so that it is never scraped half written.
+    fn write_prometheus(&self, path: &Path) -> io::Result<()> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        fs::write(&tmp, self.prometheus())?;
+        fs::rename(&tmp, path)
     }
 }
 
+/// The percentiles of the round-trip times printed with `--percentiles`.
+const PERCENTILES: [f64; 4] = [50.0, 90.0, 95.0, 99.0];
+
+/// How often the Prometheus metrics are written when pinging until interrupted.
+const PROMETHEUS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long to wait for the reverse DNS lookup of a responder.
+const REVERSE_LOOKUP_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Reverse DNS names of the responders, `None` while the lookup is running or when it failed.
+#[derive(Default)]
+struct Hostnames(Arc<Mutex<HashMap<IpAddr, Option<String>>>>);
+
+impl Hostnames {
+    /// The name of `ip` if it is known yet. The first call starts the lookup in the
+    /// background, so the receive loop never waits for it: replies are printed numerically
+    /// until it completes. A lookup which timed out is started again by the next call.
+    fn lookup(&self, ip: IpAddr) -> Option<String> {
+        match self.0.lock().entry(ip) {
+            Entry::Occupied(entry) => return entry.get().clone(),
+            Entry::Vacant(entry) => entry.insert(None),
+        };
+        let names = self.0.clone();
+        task::spawn(async move {
+            let lookup = task::spawn_blocking(move || dns_lookup::lookup_addr(&ip));
+            match time::timeout(REVERSE_LOOKUP_TIMEOUT, lookup).await {
+                Ok(Ok(Ok(name))) => {
+                    names.lock().insert(ip, Some(name));
+                }
+                Err(_) => {
+                    names.lock().remove(&ip);
+                }
+                Ok(_) => {}
+            }
+        });
+        None
+    }
+}
+
+/// How replies and errors are printed.
+#[derive(Clone, Copy)]
+struct Output {
+    /// One JSON object per line.
+    json: bool,
+    /// Prefix lines with the Unix time, like `ping -D`, or add a `timestamp` field in JSON.
+    timestamp: bool,
+}
+
+impl Output {
+    /// Print one reply, either the way `ping` does or as a JSON object.
+    fn reply(self, reply: &IcmpPacket, hostname: Option<&str>, size: usize, dur: Duration) {
+        if self.json {
+            self.json_line(json!({
+                "seq": reply.get_sequence().into_u16(),
+                "source": reply.get_source(),
+                "bytes": size,
+                "rtt_ms": dur.as_secs_f64() * 1000.0,
+                "ttl": reply.get_ttl(),
+            }));
+            return;
+        }
+        let ttl = match reply {
+            IcmpPacket::V4(_) => reply.get_ttl().map(|ttl| format!(" ttl={ttl}")),
+            IcmpPacket::V6(_) => reply.get_ttl().map(|hlim| format!(" hlim={hlim}")),
+        };
+        let source = match hostname {
+            Some(hostname) => format!("{hostname} ({})", reply.get_source()),
+            None => reply.get_source().to_string(),
+        };
+        self.line(format_args!(
+            "{size} bytes from {source}: icmp_seq={}{} time={dur:0.3?}",
+            reply.get_sequence(),
+            ttl.unwrap_or_default(),
+        ));
+    }
+
+    /// Print an error, as a JSON object with an `error` field in JSON mode.
+    fn error(self, err: &dyn std::fmt::Display) {
+        if self.json {
+            self.json_line(json!({ "error": err.to_string() }));
+        } else {
+            self.line(format_args!("{err}"));
+        }
+    }
+
+    fn line(self, line: std::fmt::Arguments) {
+        if self.timestamp {
+            let now = unix_time();
+            println!("[{}.{:06}] {line}", now.as_secs(), now.subsec_micros());
+        } else {
+            println!("{line}");
+        }
+    }
+
+    fn json_line(self, mut object: serde_json::Value) {
+        if self.timestamp {
+            object["timestamp"] = unix_time().as_secs_f64().into();
+        }
+        println!("{object}");
+    }
+}
+
+/// Time elapsed since the Unix epoch.
+fn unix_time() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+/// Bytes repeated to fill the payload.
+#[derive(Clone, Debug)]
+struct Pattern(Vec<u8>);
+
+/// Parse the payload pattern, e.g. `ff00`.
+fn parse_pattern(pattern: &str) -> Result<Pattern, String> {
+    let bytes = hex::decode(pattern).map_err(|err| format!("invalid hex pattern: {err}"))?;
+    if bytes.is_empty() {
+        return Err("empty pattern".to_string());
+    }
+    Ok(Pattern(bytes))
+}
+
 #[derive(Parser, Debug)]
 #[clap(name = "surge-ping")]
 struct Args {
@@ -105,11 +350,16 @@ struct Args {
     #[clap(short = 'i', long, default_value = "1.0")]
     interval: f64,
 
-    /// Specify the number of data bytes to be sent
+    /// Specify the number of data bytes to be sent; replies are reported with the 8 bytes of
+    /// ICMP header added, e.g. 64 bytes for the default 56
     #[clap(short = 's', long, default_value = "56")]
     size: usize,
 
-    /// Stop after sending <count> ECHO_REQUEST packets
+    /// Fill the payload with this hex byte pattern repeated, and check that replies echo it
+    #[clap(short = 'p', long, value_parser = parse_pattern)]
+    pattern: Option<Pattern>,
+
+    /// Stop after sending <count> ECHO_REQUEST packets, 0 to ping until interrupted
     #[clap(short = 'c', long, default_value = "5")]
     count: usize,
 
@@ -120,34 +370,106 @@ struct Args {
     /// Specify a timeout in seconds, beginning once the last ping is sent
     #[clap(short = 'w', long, default_value = "1.0")]
     wait_timeout: f64,
+
+    /// Set the IP Time to Live (hop limit for IPv6) of outgoing packets
+    #[clap(short = 't', long)]
+    ttl: Option<u32>,
+
+    /// Numeric output only, do not resolve the names of responders
+    #[clap(short = 'n', long)]
+    numeric: bool,
+
+    /// Quiet output, only print the summary
+    #[clap(short = 'q', long)]
+    quiet: bool,
+
+    /// Ring the terminal bell on each reply
+    #[clap(short = 'a', long)]
+    audible: bool,
+
+    /// Exit successfully after the first reply
+    #[clap(short = 'o', long)]
+    one: bool,
+
+    /// Print one JSON object per reply or error, and a JSON summary
+    #[clap(long)]
+    json: bool,
+
+    /// Print the Unix time before each reply or error
+    #[clap(short = 'D', long)]
+    timestamp: bool,
+
+    /// Leave the first <count> results out of the round-trip times of the summary, they
+    /// still count for the packet loss
+    #[clap(long, value_name = "count", default_value = "0")]
+    warmup: usize,
+
+    /// Print the 50th, 90th, 95th and 99th percentiles of the round-trip times in the summary
+    #[clap(long)]
+    percentiles: bool,
+
+    /// Write Prometheus metrics to <path> after the run, and periodically with a count of 0
+    #[clap(long, value_name = "path")]
+    prometheus: Option<PathBuf>,
 }
 
 #[tokio::main]
-async fn main() -> Result<ExitCode, Box<dyn Error>> {
+async fn main() -> ExitCode {
+    let args = Args::parse();
+    let json = args.json;
+    match run(args, &TokioResolver).await {
+        Ok(code) => code,
+        Err(err) if json => {
+            let output = Output {
+                json: true,
+                timestamp: false,
+            };
+            output.error(&err);
+            ExitCode::FAILURE
+        }
+        Err(err) => {
+            eprintln!("Error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(args: Args, resolver: &dyn Resolver) -> Result<ExitCode, Box<dyn Error>> {
     let Args {
         host,
         v4,
         v6,
         interval,
         size,
+        pattern,
         count,
         interface,
         wait_timeout,
-    } = Args::parse();
+        ttl,
+        numeric,
+        quiet,
+        audible,
+        one,
+        json,
+        timestamp,
+        warmup,
+        percentiles,
+        prometheus,
+    } = args;
+    let output = Output { json, timestamp };
 
     let is_ipv6 = v6 || (!v4 && matches!(interface, Some(IpAddr::V6(_))));
 
     if is_ipv6 && matches!(interface, Some(IpAddr::V4(_))) {
-        eprintln!("Fatal error: interface is IPv4 but ping is IPv6.");
-        return Ok(ExitCode::FAILURE);
+        return Err("Fatal error: interface is IPv4 but ping is IPv6.".into());
     }
 
-    let ip = tokio::net::lookup_host(format!("{host}:0"))
+    // Resolving `fe80::1%eth0` also yields the scope id of link-local addresses.
+    let kind = if is_ipv6 { ICMP::V6 } else { ICMP::V4 };
+    let addr = surge_ping::resolve(resolver, &host, kind)
         .await
-        .map_err(|err| format!("host lookup error: {err}"))?
-        .map(|s| s.ip())
-        .find(|ip| ip.is_ipv6() == is_ipv6)
-        .ok_or("host lookup error")?;
+        .map_err(|err| format!("host lookup error: {err}"))?;
+    let ip = addr.ip();
 
     let mut builder = Config::builder();
     if let Some(ip) = interface {
@@ -156,83 +478,170 @@ async fn main() -> Result<ExitCode, Box<dyn Error>> {
     if is_ipv6 {
         builder = builder.kind(ICMP::V6);
     }
-    let client = Client::new(&builder.build()).unwrap();
+    if let Some(ttl) = ttl {
+        builder = if is_ipv6 {
+            builder.hop_limit(ttl)
+        } else {
+            builder.ttl(ttl)
+        };
+    }
+    if pattern.is_some() {
+        builder = builder.verify_payload(true);
+    }
+    let client = Client::new(&builder.build())?;
 
-    println!("PING {host} ({ip}): {size} data bytes");
+    if !json {
+        println!("PING {host} ({ip}): {size} data bytes");
+    }
 
     let mut global_timeout = Box::pin(time::sleep(Duration::MAX));
-    let pinger = Arc::new(client.pinger(ip, PingIdentifier(random())).await);
+    let mut pinger = client.pinger(ip, PingIdentifier(random())).await;
+    if let SocketAddr::V6(addr) = addr {
+        pinger.scope_id(addr.scope_id());
+    }
+    let pinger = Arc::new(pinger);
     let (tx, mut rx) = mpsc::unbounded_channel();
 
-    tokio::spawn({
+    let sender = tokio::spawn({
         let pinger = pinger.clone();
-        let payload = (b'A'..=b'Z').cycle().take(size).collect::<Vec<_>>();
+        let payload = match &pattern {
+            Some(Pattern(pattern)) => pattern.iter().copied().cycle().take(size).collect(),
+            None => (b'A'..=b'Z').cycle().take(size).collect::<Vec<_>>(),
+        };
         let mut interval = time::interval(Duration::from_millis((interval * 1000.0) as u64));
         async move {
-            let count = count as u16;
-            for idx in 0..count {
+            // With a count of 0, send until interrupted; sequence numbers wrap after 65535.
+            let mut sent = 0;
+            let mut seq = PingSequence(NonZeroU16::MIN);
+            while count == 0 || sent < count {
                 interval.tick().await;
-                let idx = NonZeroU16::new(idx + 1).unwrap();
-                let last = idx.get() == count;
-                let send_data = pinger.ping_send(PingSequence(idx), &payload).await.unwrap();
-                tx.send((send_data, last)).unwrap();
+                sent += 1;
+                let last = sent == count;
+                let send_data = pinger.ping_send(seq, &payload).await;
+                seq = seq.next();
+                if tx.send((send_data, last)).is_err() {
+                    break;
+                }
             }
         }
     });
+    let wait_timeout = Duration::from_millis((wait_timeout * 1000.0) as u64);
 
-    let mut answer = Answer::new(host, count);
+    let mut answer = Answer::new(host);
+    answer.warmup = warmup;
+    let hostnames = Hostnames::default();
+    // The pending future keeps `fuo` from ever being exhausted, so it counts as one.
     let mut fuo = FuturesUnordered::from_iter([pending().boxed()]);
-    let mut remaining = count;
+    let mut sending = true;
     let mut success = true;
+    let mut prometheus_ticker = time::interval(PROMETHEUS_INTERVAL);
+    let write_periodically = count == 0 && prometheus.is_some();
 
     loop {
         tokio::select! {
             _ = &mut global_timeout => {
                 success = false;
-                println!("Timeout triggered when waiting for replies.");
+                if !quiet {
+                    output.error(&"Timeout triggered when waiting for replies.");
+                }
                 break;
             }
-            Some(((send_time, reply_waiter), last)) = rx.recv() => {
-                fuo.push(Box::pin(pinger.ping_recv(send_time, reply_waiter)));
+            _ = signal::ctrl_c() => {
+                // A second interrupt gives up on the outstanding replies.
+                if !sending {
+                    success = false;
+                    break;
+                }
+                sender.abort();
+                sending = false;
+                if fuo.len() == 1 {
+                    break;
+                }
+                global_timeout = Box::pin(time::sleep(wait_timeout));
+            }
+            _ = prometheus_ticker.tick(), if write_periodically => {
+                if let Some(path) = &prometheus {
+                    if let Err(err) = answer.write_prometheus(path) {
+                        output.error(&format!("cannot write Prometheus metrics: {err}"));
+                    }
+                }
+            }
+            Some((send_data, last)) = rx.recv(), if sending => {
+                answer.transmitted += 1;
+                match send_data {
+                    Ok((send_time, reply_waiter)) => {
+                        fuo.push(Box::pin(pinger.ping_recv(send_time, reply_waiter)));
+                    }
+                    Err(err) => {
+                        success = false;
+                        if !quiet {
+                            output.error(&err);
+                        }
+                        answer.update(None);
+                    }
+                }
                 if last {
-                    global_timeout = Box::pin(time::sleep(Duration::from_millis((wait_timeout * 1000.0) as u64)));
+                    sending = false;
+                    if fuo.len() == 1 {
+                        break;
+                    }
+                    global_timeout = Box::pin(time::sleep(wait_timeout));
                 }
             }
             Some(res) = fuo.next() => {
+                let replied = res.is_ok();
                 match res {
-                    Ok((IcmpPacket::V4(reply), dur)) => {
-                        println!(
-                            "{} bytes from {}: icmp_seq={} time={dur:0.3?}",
-                            reply.get_size(),
-                            reply.get_source(),
-                            reply.get_sequence(),
-                        );
-                        answer.update(Some(dur));
-                    }
-                    Ok((IcmpPacket::V6(reply), dur)) => {
-                        println!(
-                            "{} bytes from {}: icmp_seq={} time={dur:0.3?}",
-                            reply.get_size(),
-                            reply.get_source(),
-                            reply.get_sequence(),
-                        );
+                    Ok((_, dur)) if quiet => answer.update(Some(dur)),
+                    Ok((reply, dur)) => {
+                        let size = match &reply {
+                            IcmpPacket::V4(reply) => reply.get_size(),
+                            IcmpPacket::V6(reply) => reply.get_size(),
+                        };
+                        // JSON output is always numeric.
+                        let hostname = if numeric || json {
+                            None
+                        } else {
+                            hostnames.lookup(reply.get_source())
+                        };
+                        output.reply(&reply, hostname.as_deref(), size, dur);
                         answer.update(Some(dur));
                     }
                     Err(err) => {
                         success = false;
-                        println!("{err}");
+                        if !quiet {
+                            output.error(&err);
+                        }
                         answer.update(None);
                     }
                 }
-                remaining -= 1;
-                if remaining == 0 {
+                if replied && audible && !json {
+                    print!("\x07");
+                    let _ = io::stdout().flush();
+                }
+                if replied && one {
+                    // Stop sending and give up on the requests still in flight.
+                    sender.abort();
+                    pinger.reset();
+                    success = true;
+                    break;
+                }
+                if !sending && fuo.len() == 1 {
                     break;
                 }
             }
         }
     }
 
-    answer.output();
+    if json {
+        answer.output_json(percentiles);
+    } else {
+        answer.output(percentiles);
+    }
+    if let Some(path) = &prometheus {
+        answer
+            .write_prometheus(path)
+            .map_err(|err| format!("cannot write Prometheus metrics: {err}"))?;
+    }
 
     if success {
         Ok(ExitCode::SUCCESS)
@@ -240,3 +649,73 @@ async fn main() -> Result<ExitCode, Box<dyn Error>> {
         Ok(ExitCode::FAILURE)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stddev() {
+        let mut answer = Answer::new("localhost".to_string());
+        for ms in [10, 20, 30] {
+            answer.update(Some(Duration::from_millis(ms)));
+        }
+        assert!((answer.stddev() - 8.165).abs() < 0.001);
+
+        let mut answer = Answer::new("localhost".to_string());
+        for _ in 0..3 {
+            answer.update(Some(Duration::from_micros(1234)));
+        }
+        assert_eq!(answer.stddev(), 0.0);
+    }
+
+    #[test]
+    fn jitter_and_loss_burst() {
+        let mut answer = Answer::new("localhost".to_string());
+        for ms in [Some(10), None, None, Some(30), None, Some(20)] {
+            answer.update(ms.map(Duration::from_millis));
+        }
+        assert!((answer.jitter() - 15.0).abs() < 0.001);
+        assert_eq!(answer.stats.longest_loss_run(), 2);
+    }
+
+    #[test]
+    fn warmup() {
+        let mut answer = Answer::new("localhost".to_string());
+        answer.warmup = 2;
+        answer.transmitted = 4;
+        for ms in [Some(900), None, Some(10), Some(20)] {
+            answer.update(ms.map(Duration::from_millis));
+        }
+        assert_eq!(answer.received(), 3);
+        assert_eq!(answer.stats.count(), 2);
+        assert_eq!(answer.max(), 20.0);
+    }
+
+    #[test]
+    fn percentile() {
+        let mut answer = Answer::new("localhost".to_string());
+        for ms in (1..=20).rev() {
+            answer.update(Some(Duration::from_millis(ms)));
+        }
+        // Within the precision of the histogram of `Stats`.
+        for (percentile, ms) in [(50.0, 10.0), (95.0, 19.0), (99.0, 20.0)] {
+            let got = answer.percentile(percentile);
+            assert!((got / ms - 1.0).abs() < 0.025, "{}: {}", percentile, got);
+        }
+    }
+
+    #[test]
+    fn prometheus() {
+        let mut answer = Answer::new("a\"b".to_string());
+        answer.transmitted = 4;
+        answer.update(Some(Duration::from_millis(250)));
+        answer.update(Some(Duration::from_millis(500)));
+        let text = answer.prometheus();
+        assert!(text.contains("ping_rtt_seconds_sum{host=\"a\\\"b\"} 0.75\n"));
+        assert!(text.contains("ping_rtt_seconds_count{host=\"a\\\"b\"} 2\n"));
+        assert!(text.contains("ping_packets_sent_total{host=\"a\\\"b\"} 4\n"));
+        assert!(text.contains("ping_packets_received_total{host=\"a\\\"b\"} 2\n"));
+        assert!(text.contains("ping_packet_loss_ratio{host=\"a\\\"b\"} 0.5\n"));
+    }
+}
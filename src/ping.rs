@@ -1,17 +1,37 @@
 use std::{
-    net::{IpAddr, SocketAddr},
+    convert::TryInto,
+    io::{self, Write},
+    net::{IpAddr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
     num::NonZeroU16,
-    sync::atomic::{AtomicU16, Ordering},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU16, Ordering},
+        Arc, OnceLock,
+    },
+    task::{Context, Poll},
     time::{Duration, Instant},
 };
 
-use tokio::sync::oneshot::Receiver;
+use futures::{
+    future::BoxFuture,
+    stream::{FuturesUnordered, Stream, StreamExt},
+};
+use tokio::time::{self, Interval, MissedTickBehavior};
 
 use crate::{
-    client::{AsyncSocket, Reply, ReplyMap},
-    error::{Result, SurgeError},
-    icmp::{icmpv4, icmpv6, IcmpPacket, PingIdentifier, PingSequence},
+    client::{AsyncSocket, ExpectedPayload, Outstanding, PktInfo, Reply, ReplyMap, ReplyWaiter},
+    config::{Config, Icmpv6Checksum, IdentifierStrategy},
+    error::{MalformedPacketError, Result, SurgeError},
+    icmp::{
+        echo_size,
+        icmpv4::{self, IcmpTimestamps},
+        icmpv6, IcmpPacket, PingIdentifier, PingSequence,
+    },
     is_linux_icmp_socket,
+    pool::{PacketBuf, SendPool},
+    rate::RateLimiter,
+    stats::PingStats,
+    ICMP,
 };
 
 /// A Ping struct represents the state of one particular ping instance.
@@ -20,26 +40,128 @@ pub struct Pinger {
     pub ident: Option<PingIdentifier>,
     socket: AsyncSocket,
     reply_map: ReplyMap,
+    options: PingOptions,
+    scope_id: u32,
+    /// The ICMP version of the socket, checked against `host` before each send.
+    kind: ICMP,
     last_sequence: AtomicU16,
+    /// The requests of this pinger still waiting for their reply, each removed by its
+    /// `ReplyWaiter` when it resolves or is dropped.
+    outstanding: Outstanding,
+}
+
+/// What answered a [`Pinger::probe`].
+#[derive(Debug)]
+pub struct PingOutcome {
+    /// The host, or the router which reported an error.
+    pub source: IpAddr,
+    pub rtt: Duration,
+    pub kind: ProbeKind,
+    /// The whole message, e.g. for the extensions of an error message.
+    pub packet: IcmpPacket,
+}
+
+/// The kind of message which answered a [`Pinger::probe`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeKind {
+    EchoReply,
+    /// The request ran out of hops, e.g. at each hop of a traceroute.
+    TimeExceeded,
+    /// The destination is unreachable, with the ICMP code telling why, see
+    /// [`SurgeError::Unreachable`]. Includes IPv4 "fragmentation needed" (code 4).
+    Unreachable(u8),
+    /// The IPv6 request is larger than the MTU of a link, which this is.
+    PacketTooBig(u32),
+}
+
+/// Outcome of [`Pinger::sweep`].
+#[derive(Debug)]
+pub struct Sweep {
+    /// The round trip time, or the error, for each payload size in the order sent.
+    pub results: Vec<(usize, Result<Duration>)>,
+}
+
+impl Sweep {
+    /// The largest payload size which got a reply.
+    pub fn max_size(&self) -> Option<usize> {
+        self.results
+            .iter()
+            .filter(|(_, rtt)| rtt.is_ok())
+            .map(|(size, _)| *size)
+            .max()
+    }
+}
+
+/// Settings of the `Client` which affect how its pingers send and match requests.
+#[derive(Debug, Clone)]
+pub(crate) struct PingOptions {
+    pub verify_payload: bool,
+    pub allow_flood: bool,
+    /// Shared by all the pingers of the client.
+    pub limiter: Option<Arc<RateLimiter>>,
+    pub identifier_strategy: Option<IdentifierStrategy>,
+    pub embed_timestamp: bool,
+    pub zero_based_sequence: bool,
+    pub icmpv6_checksum: Icmpv6Checksum,
+    /// Shared by all the pingers of the client.
+    pub send_pool: Option<Arc<SendPool>>,
+    pub adaptive: bool,
+    /// Sent as the flow information of IPv6 destinations, see `Config::builder().flow_label()`.
+    pub flow_label: Option<u32>,
+}
+
+impl PingOptions {
+    pub(crate) fn new(config: &Config) -> Self {
+        PingOptions {
+            verify_payload: config.verify_payload,
+            allow_flood: config.allow_flood,
+            limiter: config
+                .max_pps
+                .filter(|&pps| pps > 0)
+                .map(|pps| Arc::new(RateLimiter::new(pps))),
+            identifier_strategy: config.identifier_strategy,
+            embed_timestamp: config.embed_timestamp,
+            zero_based_sequence: config.zero_based_sequence,
+            icmpv6_checksum: config.icmpv6_checksum,
+            send_pool: config.send_pool.then(|| Arc::new(SendPool::default())),
+            adaptive: config.adaptive,
+            flow_label: config.flow_label,
+        }
+    }
+
+    /// The identifier of a new pinger, `Config::identifier_strategy` overriding `hint`.
+    pub(crate) fn identifier(&self, hint: PingIdentifier) -> PingIdentifier {
+        match self.identifier_strategy {
+            Some(IdentifierStrategy::Fixed(ident)) => ident,
+            Some(IdentifierStrategy::Random) => PingIdentifier(rand::random()),
+            None => hint,
+        }
+    }
 }
 
 impl Drop for Pinger {
     fn drop(&mut self) {
-        if let Some(sequence) = NonZeroU16::new(self.last_sequence.load(Ordering::Relaxed)) {
-            // Ensure no reply waiter is left hanging if this pinger is dropped while
-            // waiting for a reply.
+        // Ensure no reply waiter is left hanging if this pinger is dropped while
+        // waiting for replies. Only its own: without identifiers, another pinger of the
+        // host may have registered a sequence number this one used before.
+        for (seq, generation) in self.outstanding.lock().drain() {
             self.reply_map
-                .remove(self.host, self.ident, sequence.into());
+                .remove(self.host, self.ident, seq, generation);
         }
     }
 }
 
 impl Pinger {
+    /// Maximum number of requests in flight in [`flood`](Self::flood).
+    pub const FLOOD_WINDOW: usize = 100;
+    const FLOOD_INTERVAL: Duration = Duration::from_millis(10);
+
     pub(crate) fn new(
         host: IpAddr,
         ident_hint: PingIdentifier,
         socket: AsyncSocket,
         response_map: ReplyMap,
+        options: PingOptions,
     ) -> Pinger {
         let ident = if is_linux_icmp_socket!(socket.get_type()) {
             None
@@ -50,34 +172,443 @@ impl Pinger {
         Pinger {
             host,
             ident,
+            kind: socket.kind(),
             socket,
             reply_map: response_map,
+            options,
+            scope_id: 0,
             last_sequence: 0.into(),
+            outstanding: Outstanding::default(),
+        }
+    }
+
+    /// Whether sessions and streams wait for the previous request to complete before
+    /// sending the next one, see `Config::builder().adaptive()`.
+    pub(crate) fn is_adaptive(&self) -> bool {
+        self.options.adaptive
+    }
+
+    /// Set the scope id (interface index) of an IPv6 link-local host such as `fe80::1%eth0`.
+    /// Without it link-local hosts are unroutable on a machine with several interfaces.
+    pub fn scope_id(&mut self, scope_id: u32) -> &mut Pinger {
+        self.scope_id = scope_id;
+        self
+    }
+
+    /// Fail with [`SurgeError::AddressFamilyMismatch`] if the host cannot be reached from
+    /// the socket, the kernel would only report an obscure error on send.
+    pub(crate) fn check_family(&self) -> Result<()> {
+        match (self.host, self.kind) {
+            (IpAddr::V4(_), ICMP::V4) | (IpAddr::V6(_), ICMP::V6) => Ok(()),
+            (host, kind) => Err(SurgeError::AddressFamilyMismatch { host, kind }),
+        }
+    }
+
+    fn target(&self) -> SocketAddr {
+        match self.host {
+            IpAddr::V4(host) => SocketAddr::V4(SocketAddrV4::new(host, 0)),
+            IpAddr::V6(host) => {
+                // `sin6_flowinfo` is in network byte order.
+                let flowinfo = self.options.flow_label.unwrap_or(0).to_be();
+                SocketAddr::V6(SocketAddrV6::new(host, 0, flowinfo, self.scope_id))
+            }
         }
     }
 
     /// Send Ping request with sequence number.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(host = %self.host, ident = ?self.ident, seq = %seq))
+    )]
     pub async fn ping(&self, seq: PingSequence, payload: &[u8]) -> Result<(IcmpPacket, Duration)> {
         let (send_time, reply_waiter) = self.ping_send(seq, payload).await?;
         self.ping_recv(send_time, reply_waiter).await
     }
 
+    /// Like [`ping`](Self::ping), with the instant the reply was received as well, as taken
+    /// by the receive task: to correlate replies with other events without the error a
+    /// later `Instant::now()` would add.
+    pub async fn ping_full(
+        &self,
+        seq: PingSequence,
+        payload: &[u8],
+    ) -> Result<(IcmpPacket, Duration, Instant)> {
+        let (send_time, reply_waiter) = self.ping_send(seq, payload).await?;
+        self.ping_recv_full(send_time, reply_waiter).await
+    }
+
+    /// Like [`ping`](Self::ping), but send the request from the local address `source` of
+    /// the host, e.g. to test reachability from each of its prefixes with a single client.
+    /// IPv6 hosts and raw sockets only, Linux only.
+    pub async fn ping_from(
+        &self,
+        source: Ipv6Addr,
+        seq: PingSequence,
+        payload: &[u8],
+    ) -> Result<(IcmpPacket, Duration)> {
+        if !self.host.is_ipv6() {
+            return Err(SurgeError::Ipv6Only);
+        }
+        let pktinfo = PktInfo {
+            source: Some(source.into()),
+            if_index: 0,
+        };
+        let (send_time, reply_waiter) = self.send_request(seq, payload, Some(pktinfo)).await?;
+        self.ping_recv(send_time, reply_waiter).await
+    }
+
+    /// Like [`ping`](Self::ping), but send the request out of the interface of index
+    /// `if_index` whatever the routing table says, e.g. to probe each uplink with a single
+    /// client. IPv6 needs a raw socket, Linux only.
+    pub async fn ping_via(
+        &self,
+        seq: PingSequence,
+        payload: &[u8],
+        if_index: u32,
+    ) -> Result<(IcmpPacket, Duration)> {
+        let pktinfo = PktInfo {
+            source: None,
+            if_index,
+        };
+        let (send_time, reply_waiter) = self.send_request(seq, payload, Some(pktinfo)).await?;
+        self.ping_recv(send_time, reply_waiter).await
+    }
+
+    /// Like [`ping`](Self::ping), with a payload owned by the future instead of borrowed, e.g.
+    /// to pipeline sends out of a shared buffer. The reply is verified against `payload`
+    /// itself, without a copy, see `Config::builder().verify_payload()`.
+    #[cfg(feature = "bytes")]
+    pub async fn ping_bytes(
+        &self,
+        seq: PingSequence,
+        payload: bytes::Bytes,
+    ) -> Result<(IcmpPacket, Duration)> {
+        if self.options.embed_timestamp {
+            // The payload sent is a stamped copy anyway.
+            return self.ping(seq, &payload).await;
+        }
+        let expected_payload = self
+            .options
+            .verify_payload
+            .then(|| ExpectedPayload::Shared(payload.clone()));
+        let (send_time, reply_waiter) = self
+            .send_payload(seq, &payload, expected_payload, None)
+            .await?;
+        self.ping_recv(send_time, reply_waiter).await
+    }
+
+    /// Send an echo request and report whatever answers it, the echo reply or an ICMP error
+    /// message from a router, e.g. for a traceroute to take a Time Exceeded as a hop rather
+    /// than as an error like [`ping`](Self::ping) does. ICMP error messages are only
+    /// delivered on raw sockets.
+    pub async fn probe(&self, seq: PingSequence, payload: &[u8]) -> Result<PingOutcome> {
+        let (send_time, reply_waiter) = self.ping_send(seq, payload).await?;
+        let reply = reply_waiter.await?;
+        let kind = match reply.packet.to_error() {
+            None => ProbeKind::EchoReply,
+            Some(SurgeError::TimeExceeded { .. }) => ProbeKind::TimeExceeded,
+            Some(SurgeError::Unreachable { code, .. }) => ProbeKind::Unreachable(code),
+            Some(SurgeError::FragmentationNeeded { .. }) => {
+                ProbeKind::Unreachable(reply.packet.get_icmp_code())
+            }
+            Some(SurgeError::PacketTooBig { mtu, .. }) => ProbeKind::PacketTooBig(mtu),
+            Some(err) => return Err(err),
+        };
+        Ok(PingOutcome {
+            source: reply.packet.get_source(),
+            rtt: self.rtt(send_time, &reply),
+            kind,
+            packet: reply.packet,
+        })
+    }
+
+    /// Send Ping request with the sequence number following the last one sent by this pinger,
+    /// wrapping from `u16::MAX` back to 1.
+    pub async fn ping_next(&self, payload: &[u8]) -> Result<(IcmpPacket, Duration)> {
+        self.ping(self.next_sequence(), payload).await
+    }
+
+    fn next_sequence(&self) -> PingSequence {
+        let next = |last| match NonZeroU16::new(last) {
+            Some(last) => PingSequence(last).next(),
+            None => PingSequence(NonZeroU16::MIN),
+        };
+        let last = self
+            .last_sequence
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |last| {
+                Some(next(last).into_u16())
+            })
+            .unwrap();
+        next(last)
+    }
+
+    /// Send Ping request with sequence number and wait at most `timeout` for the reply.
+    ///
+    /// On expiry the reply waiter is removed and [`SurgeError::Timeout`] is returned.
+    pub async fn ping_timeout(
+        &self,
+        seq: PingSequence,
+        payload: &[u8],
+        timeout: Duration,
+    ) -> Result<(IcmpPacket, Duration)> {
+        self.ping_deadline(seq, payload, Instant::now() + timeout)
+            .await
+    }
+
+    /// Ping up to `attempts` times (at least once), waiting at most `per_attempt_timeout`
+    /// for each reply, until one is not a [`SurgeError::Timeout`]: the result of the first
+    /// success, the first other error, or the timeout of the last attempt. Each attempt takes
+    /// the next sequence number, like [`ping_next`](Self::ping_next), so that a late reply to
+    /// an earlier attempt is not taken for the answer to the current one.
+    ///
+    /// After a timed out attempt the next one waits for `backoff`, doubled for each further
+    /// attempt, so that a host which is briefly unreachable is not hammered.
+    pub async fn ping_retry(
+        &self,
+        payload: &[u8],
+        attempts: u32,
+        per_attempt_timeout: Duration,
+        backoff: Duration,
+    ) -> Result<(IcmpPacket, Duration)> {
+        let mut attempts = attempts.max(1);
+        let mut backoff = backoff;
+        loop {
+            let seq = self.next_sequence();
+            attempts -= 1;
+            match self.ping_timeout(seq, payload, per_attempt_timeout).await {
+                Err(SurgeError::Timeout { .. }) if attempts > 0 => {
+                    time::sleep(backoff).await;
+                    backoff = backoff.saturating_mul(2);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Send Ping request with sequence number and wait for the reply until `deadline`.
+    ///
+    /// On expiry the reply waiter is removed and [`SurgeError::Timeout`] is returned.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip_all, fields(host = %self.host, ident = ?self.ident, seq = %seq))
+    )]
+    pub async fn ping_deadline(
+        &self,
+        seq: PingSequence,
+        payload: &[u8],
+        deadline: Instant,
+    ) -> Result<(IcmpPacket, Duration)> {
+        let (send_time, reply_waiter) = self.ping_send(seq, payload).await?;
+        let reply =
+            time::timeout_at(deadline.into(), self.ping_recv(send_time, reply_waiter)).await;
+        match reply {
+            Ok(reply) => reply,
+            Err(_) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("echo request timed out");
+                Err(SurgeError::Timeout {
+                    host: self.host,
+                    seq,
+                })
+            }
+        }
+    }
+
+    /// Sweep the payload size, like `ping -g start -G end -h step`: send one request of each
+    /// size from `start` to `end` included, with the following sequence numbers, waiting at
+    /// most `timeout` for each reply.
+    ///
+    /// With `Config::builder().dont_fragment(true)`, [`Sweep::max_size`] estimates the path MTU.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `step` is 0.
+    pub async fn sweep(&self, start: usize, end: usize, step: usize, timeout: Duration) -> Sweep {
+        let mut results = Vec::new();
+        for size in (start..=end).step_by(step) {
+            let payload = vec![0; size];
+            let rtt = self
+                .ping_timeout(self.next_sequence(), &payload, timeout)
+                .await
+                .map(|(_, rtt)| rtt);
+            results.push((size, rtt));
+        }
+        Sweep { results }
+    }
+
+    /// Ping a broadcast address, like `ping -b`, and collect every echo reply received
+    /// within `window`, as `(responder, rtt)` in the order they arrived.
+    ///
+    /// Requires `Config::builder().broadcast(true)`. Hosts often ignore broadcast pings,
+    /// e.g. Linux unless `net.ipv4.icmp_echo_ignore_broadcasts` is 0.
+    pub async fn ping_broadcast(
+        &self,
+        payload: &[u8],
+        window: Duration,
+    ) -> Result<Vec<(IpAddr, Duration)>> {
+        self.collect_replies(payload, window).await
+    }
+
+    /// Ping a multicast address, such as the IPv6 all-nodes address `ff02::1`, and collect
+    /// every echo reply received within `window`, as `(responder, rtt)` in the order they
+    /// arrived.
+    ///
+    /// Link-local multicast addresses need the outgoing interface, given by the scope id
+    /// of the pinger or `Config::builder().multicast_interface()`.
+    pub async fn ping_multicast(
+        &self,
+        payload: &[u8],
+        window: Duration,
+    ) -> Result<Vec<(IpAddr, Duration)>> {
+        self.collect_replies(payload, window).await
+    }
+
+    /// Send one request and gather all the replies to it, whichever host they come from.
+    /// Replies another pinger of the client waits for, same sequence number or not, are
+    /// left to it.
+    async fn collect_replies(
+        &self,
+        payload: &[u8],
+        window: Duration,
+    ) -> Result<Vec<(IpAddr, Duration)>> {
+        let seq = self.next_sequence();
+        let mut replies = self.reply_map.new_collector(self.host, self.ident, seq)?;
+        let _guard = CollectorGuard {
+            reply_map: &self.reply_map,
+            ident: self.ident,
+            seq,
+        };
+
+        self.send_ping(seq, payload).await?;
+        let send_time = Instant::now();
+
+        let mut responders = Vec::new();
+        let collect = async {
+            while let Some(reply) = replies.recv().await {
+                if self.options.verify_payload && reply.packet.get_payload() != payload {
+                    continue;
+                }
+                let rtt = reply.timestamp.saturating_duration_since(send_time);
+                responders.push((reply.packet.get_source(), rtt));
+            }
+        };
+        let _ = time::timeout(window, collect).await;
+        Ok(responders)
+    }
+
+    /// Flood ping, like `ping -f`: send `count` requests, each as soon as a reply comes back
+    /// or every 10ms, whichever comes first, with at most [`FLOOD_WINDOW`](Self::FLOOD_WINDOW)
+    /// requests in flight. A dot is printed for every request and erased for every reply,
+    /// so the dots left on screen are the lost packets.
+    ///
+    /// Requires `Config::builder().allow_flood(true)`, fails with
+    /// [`SurgeError::FloodNotAllowed`] otherwise.
+    pub async fn flood(&self, payload: &[u8], count: usize) -> Result<PingStats> {
+        if !self.options.allow_flood {
+            return Err(SurgeError::FloodNotAllowed);
+        }
+
+        let mut stats = PingStats::new();
+        let mut in_flight = FuturesUnordered::new();
+        let mut ticker = time::interval(Self::FLOOD_INTERVAL);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut sent = 0;
+
+        let send = |seq: PingSequence| async move {
+            let (send_time, reply_waiter) = self.ping_send(seq, payload).await?;
+            print!(".");
+            let _ = io::stdout().flush();
+            Ok::<_, SurgeError>(async move {
+                let reply = time::timeout(
+                    PingStream::DEFAULT_TIMEOUT,
+                    self.ping_recv(send_time, reply_waiter),
+                )
+                .await;
+                reply.ok().and_then(|reply| reply.ok())
+            })
+        };
+
+        while sent < count || !in_flight.is_empty() {
+            let can_send = sent < count && in_flight.len() < Self::FLOOD_WINDOW;
+            tokio::select! {
+                _ = ticker.tick(), if can_send => {
+                    in_flight.push(send(self.next_sequence()).await?);
+                    sent += 1;
+                }
+                Some(reply) = in_flight.next() => {
+                    let rtt = reply.map(|(_, rtt)| rtt);
+                    if rtt.is_some() {
+                        print!("\x08 \x08");
+                        let _ = io::stdout().flush();
+                    }
+                    stats.record(rtt);
+                    if sent < count && in_flight.len() < Self::FLOOD_WINDOW {
+                        in_flight.push(send(self.next_sequence()).await?);
+                        sent += 1;
+                    }
+                }
+            }
+        }
+
+        Ok(stats)
+    }
+
+    /// Turn this pinger into a [`PingStream`] sending a request every `interval` and yielding
+    /// the replies as they arrive. Sequence numbers start at 1 and wrap around after `u16::MAX`.
+    pub fn into_stream(self, interval: Duration, payload: Vec<u8>) -> PingStream {
+        PingStream::new(self, interval, payload)
+    }
+
     pub async fn ping_send(
         &self,
         seq: PingSequence,
         payload: &[u8],
-    ) -> Result<(Instant, Receiver<Reply>)> {
+    ) -> Result<(Instant, ReplyWaiter)> {
+        self.send_request(seq, payload, None).await
+    }
+
+    /// Register for the reply and send the echo request, with `pktinfo` if set.
+    async fn send_request(
+        &self,
+        seq: PingSequence,
+        payload: &[u8],
+        pktinfo: Option<PktInfo>,
+    ) -> Result<(Instant, ReplyWaiter)> {
+        let stamped;
+        let payload = if self.options.embed_timestamp {
+            stamped = embed_timestamp(payload)?;
+            &stamped[..]
+        } else {
+            payload
+        };
+
+        let expected_payload = self
+            .options
+            .verify_payload
+            .then(|| ExpectedPayload::Owned(payload.to_vec()));
+        self.send_payload(seq, payload, expected_payload, pktinfo)
+            .await
+    }
+
+    /// Register for the reply, which must echo `expected_payload` if set, and send the echo
+    /// request carrying `payload`.
+    async fn send_payload(
+        &self,
+        seq: PingSequence,
+        payload: &[u8],
+        expected_payload: Option<ExpectedPayload>,
+        pktinfo: Option<PktInfo>,
+    ) -> Result<(Instant, ReplyWaiter)> {
         // Register to wait for a reply
-        let reply_waiter = self.reply_map.new_waiter(self.host, self.ident, seq)?;
+        let reply_waiter = self.register(seq, expected_payload)?;
 
         // Send actual packet
-        if let Err(e) = self.send_ping(seq, payload).await {
-            self.reply_map.remove(self.host, self.ident, seq);
-            return Err(e);
-        }
+        self.send_echo(seq, payload, pktinfo).await?;
 
         let send_time = Instant::now();
         self.last_sequence.store(seq.0.get(), Ordering::Relaxed);
+        #[cfg(feature = "tracing")]
+        tracing::debug!(size = payload.len(), "echo request sent");
 
         Ok((send_time, reply_waiter))
     }
@@ -85,34 +616,610 @@ impl Pinger {
     pub async fn ping_recv(
         &self,
         send_time: Instant,
-        reply_waiter: Receiver<Reply>,
+        reply_waiter: ReplyWaiter,
     ) -> Result<(IcmpPacket, Duration)> {
-        let reply = reply_waiter.await.map_err(|_| SurgeError::NetworkError)?;
-        let duration = reply.timestamp.saturating_duration_since(send_time);
-        Ok((reply.packet, duration))
+        self.ping_recv_full(send_time, reply_waiter)
+            .await
+            .map(|(packet, rtt, _)| (packet, rtt))
+    }
+
+    /// Like [`ping_recv`](Self::ping_recv), with the instant the reply was received as well.
+    pub async fn ping_recv_full(
+        &self,
+        send_time: Instant,
+        reply_waiter: ReplyWaiter,
+    ) -> Result<(IcmpPacket, Duration, Instant)> {
+        let result = match reply_waiter.await {
+            Ok(reply) => match reply.packet.to_error() {
+                Some(err) => Err(err),
+                None => {
+                    let duration = self.rtt(send_time, &reply);
+                    Ok((reply.packet, duration, reply.timestamp))
+                }
+            },
+            Err(err) => Err(err),
+        };
+        #[cfg(feature = "tracing")]
+        match &result {
+            Ok((_, rtt, _)) => tracing::debug!(?rtt, "echo reply received"),
+            Err(err) => tracing::debug!(%err, "echo request failed"),
+        }
+        result
+    }
+
+    /// The round trip time of `reply`, from the timestamp embedded in its payload if any.
+    fn rtt(&self, send_time: Instant, reply: &Reply) -> Duration {
+        let send_time = self
+            .options
+            .embed_timestamp
+            .then(|| embedded_timestamp(reply.packet.get_payload()))
+            .flatten()
+            .filter(|&embedded| embedded <= reply.timestamp)
+            .unwrap_or(send_time);
+        reply.timestamp.saturating_duration_since(send_time)
+    }
+
+    /// Send an ICMP Timestamp Request and wait for the reply. IPv4 only, and needs a raw
+    /// socket: Linux ICMP sockets only carry echo messages.
+    pub async fn timestamp(&self, seq: PingSequence) -> Result<IcmpTimestamps> {
+        if !self.host.is_ipv4() {
+            return Err(SurgeError::Ipv4Only);
+        }
+
+        let reply_waiter = self.register(seq, None)?;
+        let mut packet = icmpv4::make_icmpv4_timestamp_packet(
+            self.ident.unwrap_or(PingIdentifier(0)),
+            seq.to_wire(self.options.zero_based_sequence),
+            self.socket.get_type(),
+            icmpv4::ms_since_midnight(),
+        )?;
+        self.send_packet(&mut packet).await?;
+        self.last_sequence.store(seq.0.get(), Ordering::Relaxed);
+
+        let reply = reply_waiter.await?;
+        if let Some(err) = reply.packet.to_error() {
+            return Err(err);
+        }
+        match reply.packet {
+            IcmpPacket::V4(packet) => packet.get_timestamps(),
+            IcmpPacket::V6(_) => None,
+        }
+        .ok_or_else(|| MalformedPacketError::NotTimestampReply.into())
     }
 
     /// Send a ping packet (useful, when you don't need a reply).
     pub async fn send_ping(&self, seq: PingSequence, payload: &[u8]) -> Result<()> {
+        self.send_echo(seq, payload, None).await
+    }
+
+    async fn send_echo(
+        &self,
+        seq: PingSequence,
+        payload: &[u8],
+        pktinfo: Option<PktInfo>,
+    ) -> Result<()> {
+        self.check_family()?;
         // Create and send ping packet.
-        let mut packet = match self.host {
-            IpAddr::V4(_) => icmpv4::make_icmpv4_echo_packet(
+        let seq = seq.to_wire(self.options.zero_based_sequence);
+        let mut packet =
+            PacketBuf::new(self.options.send_pool.as_deref(), echo_size(payload.len()));
+        match self.host {
+            IpAddr::V4(_) => icmpv4::write_icmpv4_echo_packet(
+                &mut packet,
                 self.ident.unwrap_or(PingIdentifier(0)),
                 seq,
                 self.socket.get_type(),
                 payload,
             )?,
-            IpAddr::V6(_) => icmpv6::make_icmpv6_echo_packet(
-                self.ident.unwrap_or(PingIdentifier(0)),
-                seq,
-                payload,
-            )?,
+            IpAddr::V6(host) => {
+                icmpv6::write_icmpv6_echo_packet(
+                    &mut packet,
+                    self.ident.unwrap_or(PingIdentifier(0)),
+                    seq,
+                    payload,
+                )?;
+                if self.options.icmpv6_checksum == Icmpv6Checksum::Manual {
+                    let source = match pktinfo.and_then(|pktinfo| pktinfo.source) {
+                        Some(IpAddr::V6(source)) => source,
+                        _ => match self.socket.source_for(self.target())? {
+                            IpAddr::V6(source) => source,
+                            IpAddr::V4(_) => return Err(SurgeError::Ipv6Only),
+                        },
+                    };
+                    icmpv6::set_checksum(&mut packet, source, host)?;
+                }
+            }
+        }
+        self.socket.check_mtu(packet.len())?;
+
+        self.send_packet_with(&mut packet, pktinfo).await
+    }
+
+    /// Abandon every request of this pinger still waiting for a reply, e.g. after a network
+    /// outage: each of them fails with [`SurgeError::Timeout`].
+    pub fn reset(&self) {
+        let outstanding: Vec<_> = self.outstanding.lock().drain().collect();
+        for (seq, generation) in outstanding {
+            if let Some(waiter) = self
+                .reply_map
+                .remove(self.host, self.ident, seq, generation)
+            {
+                waiter.fail(|| SurgeError::Timeout {
+                    host: self.host,
+                    seq,
+                });
+            }
+        }
+    }
+
+    /// Abandon the request `seq` if it is still waiting for a reply: it fails with
+    /// [`SurgeError::Cancelled`] and a late reply is counted as unmatched. Returns whether
+    /// there was such a request.
+    pub fn cancel(&self, seq: PingSequence) -> bool {
+        let Some(generation) = self.outstanding.lock().remove(&seq) else {
+            return false;
         };
+        match self
+            .reply_map
+            .remove(self.host, self.ident, seq, generation)
+        {
+            Some(waiter) => {
+                waiter.fail(|| SurgeError::Cancelled {
+                    host: self.host,
+                    seq,
+                });
+                true
+            }
+            None => false,
+        }
+    }
 
-        self.socket
-            .send_to(&mut packet, &SocketAddr::new(self.host, 0))
-            .await?;
+    /// Register to wait for the reply to `seq`, see `ReplyMap::new_waiter`.
+    fn register(&self, seq: PingSequence, payload: Option<ExpectedPayload>) -> Result<ReplyWaiter> {
+        let reply_waiter = self
+            .reply_map
+            .new_waiter(self.host, self.ident, seq, payload)?;
+        Ok(reply_waiter.track(&self.outstanding))
+    }
 
+    /// Send a packet to the host, waiting for the rate limiter first if there is one.
+    async fn send_packet(&self, packet: &mut [u8]) -> Result<()> {
+        self.check_family()?;
+        self.send_packet_with(packet, None).await
+    }
+
+    /// Send a packet to the host with `pktinfo` if set, see [`send_packet`](Self::send_packet).
+    async fn send_packet_with(&self, packet: &mut [u8], pktinfo: Option<PktInfo>) -> Result<()> {
+        if let Some(limiter) = &self.options.limiter {
+            limiter.acquire().await;
+        }
+        match pktinfo {
+            Some(pktinfo) => {
+                self.socket
+                    .send_to_with_pktinfo(packet, self.target(), pktinfo)
+                    .await?
+            }
+            None => self.socket.send_to(packet, &self.target()).await?,
+        };
         Ok(())
     }
 }
+
+/// Reference point of the timestamps embedded in payloads, `Instant` has no absolute value.
+fn timestamp_epoch() -> Instant {
+    static EPOCH: OnceLock<Instant> = OnceLock::new();
+    *EPOCH.get_or_init(Instant::now)
+}
+
+/// Copy of `payload` starting with the current time, see `Config::embed_timestamp`.
+fn embed_timestamp(payload: &[u8]) -> Result<Vec<u8>> {
+    if payload.len() < 8 {
+        return Err(SurgeError::PayloadTooShortForTimestamp { len: payload.len() });
+    }
+    let nanos = timestamp_epoch().elapsed().as_nanos() as u64;
+    let mut stamped = payload.to_vec();
+    stamped[..8].copy_from_slice(&nanos.to_be_bytes());
+    Ok(stamped)
+}
+
+/// The send time written by `embed_timestamp` at the start of an echoed payload.
+fn embedded_timestamp(payload: &[u8]) -> Option<Instant> {
+    let nanos = u64::from_be_bytes(payload.get(..8)?.try_into().ok()?);
+    timestamp_epoch().checked_add(Duration::from_nanos(nanos))
+}
+
+type PendingReply = BoxFuture<'static, Result<(IcmpPacket, Duration)>>;
+
+/// Stream of replies returned by [`Pinger::into_stream`].
+///
+/// With `Config::builder().adaptive()` a request is sent once the previous one completes,
+/// and no sooner than the interval after it. Each request which is not answered within the timeout (1 second by default) yields a
+/// [`SurgeError::Timeout`]. Dropping the stream removes the waiters of outstanding requests.
+pub struct PingStream {
+    pinger: Arc<Pinger>,
+    payload: Arc<[u8]>,
+    interval: Interval,
+    timeout: Duration,
+    seq: PingSequence,
+    sending: Option<BoxFuture<'static, Result<PendingReply>>>,
+    pending: FuturesUnordered<PendingReply>,
+}
+
+impl PingStream {
+    const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+    fn new(pinger: Pinger, interval: Duration, payload: Vec<u8>) -> Self {
+        let mut interval = time::interval(interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        PingStream {
+            pinger: Arc::new(pinger),
+            payload: payload.into(),
+            interval,
+            timeout: Self::DEFAULT_TIMEOUT,
+            seq: PingSequence(NonZeroU16::MIN),
+            sending: None,
+            pending: FuturesUnordered::new(),
+        }
+    }
+
+    /// Set how long to wait for each reply.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    fn send_next(&mut self) -> BoxFuture<'static, Result<PendingReply>> {
+        let pinger = self.pinger.clone();
+        let payload = self.payload.clone();
+        let timeout = self.timeout;
+        let seq = self.seq;
+        self.seq = seq.next();
+
+        Box::pin(async move {
+            let (send_time, reply_waiter) = pinger.ping_send(seq, &payload).await?;
+            let recv: PendingReply = Box::pin(async move {
+                let reply = time::timeout(timeout, pinger.ping_recv(send_time, reply_waiter)).await;
+                reply.unwrap_or(Err(SurgeError::Timeout {
+                    host: pinger.host,
+                    seq,
+                }))
+            });
+            Ok(recv)
+        })
+    }
+}
+
+impl Stream for PingStream {
+    type Item = Result<(IcmpPacket, Duration)>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            // Adaptive streams keep at most one request in flight.
+            let idle = !this.pinger.is_adaptive() || this.pending.is_empty();
+            if this.sending.is_none() && idle && this.interval.poll_tick(cx).is_ready() {
+                this.sending = Some(this.send_next());
+                if this.pinger.is_adaptive() {
+                    // The interval counts from this request.
+                    this.interval.reset();
+                }
+            }
+            if let Some(sending) = &mut this.sending {
+                if let Poll::Ready(sent) = sending.as_mut().poll(cx) {
+                    this.sending = None;
+                    match sent {
+                        Ok(recv) => this.pending.push(recv),
+                        Err(err) => return Poll::Ready(Some(Err(err))),
+                    }
+                    continue;
+                }
+            }
+            return match this.pending.poll_next_unpin(cx) {
+                Poll::Ready(Some(reply)) => Poll::Ready(Some(reply)),
+                _ => Poll::Pending,
+            };
+        }
+    }
+}
+
+/// Removes the reply collector of `Pinger::collect_replies`, even if it is cancelled.
+struct CollectorGuard<'a> {
+    reply_map: &'a ReplyMap,
+    ident: Option<PingIdentifier>,
+    seq: PingSequence,
+}
+
+impl Drop for CollectorGuard<'_> {
+    fn drop(&mut self) {
+        self.reply_map.remove_collector(self.ident, self.seq);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn embedded_timestamp_round_trip() {
+        let stamped = embed_timestamp(&[0xaa; 12]).unwrap();
+        assert_eq!(&stamped[8..], &[0xaa; 4]);
+        let embedded = embedded_timestamp(&stamped).unwrap();
+        assert!(embedded >= timestamp_epoch() && embedded <= Instant::now());
+
+        assert!(matches!(
+            embed_timestamp(&[0; 7]),
+            Err(SurgeError::PayloadTooShortForTimestamp { len: 7 })
+        ));
+        assert!(embedded_timestamp(&[0; 7]).is_none());
+    }
+
+    #[tokio::test]
+    async fn drop_removes_every_waiter() {
+        let client = crate::Client::test_echo(|_| true).await;
+        let pinger = client
+            .pinger("192.0.2.1".parse().unwrap(), PingIdentifier(1))
+            .await;
+        let _waiters: Vec<_> = (1..=3)
+            .map(|seq| pinger.register(PingSequence(NonZeroU16::new(seq).unwrap()), None))
+            .collect();
+        assert_eq!(client.pending_count(), 3);
+        drop(pinger);
+        assert_eq!(client.pending_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn cancel() {
+        let client = crate::Client::test_echo(|_| true).await;
+        let pinger = client
+            .pinger("192.0.2.1".parse().unwrap(), PingIdentifier(1))
+            .await;
+        let seq = PingSequence(NonZeroU16::MIN);
+        let waiter = pinger.register(seq, None).unwrap();
+        assert!(pinger.cancel(seq));
+        assert!(!pinger.cancel(seq));
+        assert_eq!(client.pending_count(), 0);
+        assert!(matches!(
+            pinger.ping_recv(Instant::now(), waiter).await,
+            Err(SurgeError::Cancelled { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn dropped_ping_recv_removes_its_waiter() {
+        let client = crate::Client::test_echo(|_| true).await;
+        let pinger = Arc::new(
+            client
+                .pinger("192.0.2.1".parse().unwrap(), PingIdentifier(1))
+                .await,
+        );
+        let tasks: Vec<_> = (1..=100)
+            .map(|seq| {
+                let waiter = pinger
+                    .register(PingSequence(NonZeroU16::new(seq).unwrap()), None)
+                    .unwrap();
+                let pinger = pinger.clone();
+                tokio::spawn(async move { pinger.ping_recv(Instant::now(), waiter).await })
+            })
+            .collect();
+        assert_eq!(client.pending_count(), 100);
+        for task in tasks {
+            task.abort();
+            assert!(task.await.unwrap_err().is_cancelled());
+        }
+        assert_eq!(client.pending_count(), 0);
+    }
+
+    // Other platforms expect the IP header in front of the ICMP message.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[tokio::test]
+    async fn probe() {
+        let (peer, client) = crate::Client::test_transport_pair().await;
+        // Answer the first request, report the others as out of hops.
+        tokio::spawn(async move {
+            let mut buf = [0; 2048];
+            while let Ok(len) = peer.recv(&mut buf).await {
+                if buf[7] == 1 {
+                    buf[0] = 0;
+                    let _ = peer.send(&buf[..len]).await;
+                } else {
+                    // type 11, the IPv4 header of the request to 127.0.0.1, its first 8 bytes.
+                    let mut time_exceeded = hex::decode(concat!(
+                        "0b00000000000000",
+                        "4500001c00000000400100007f0000017f000001",
+                    ))
+                    .unwrap();
+                    time_exceeded.extend_from_slice(&buf[..8]);
+                    let _ = peer.send(&time_exceeded).await;
+                }
+            }
+        });
+
+        let pinger = client
+            .pinger("127.0.0.1".parse().unwrap(), PingIdentifier(1))
+            .await;
+        let outcome = pinger
+            .probe(PingSequence(NonZeroU16::MIN), b"payload")
+            .await
+            .unwrap();
+        assert_eq!(outcome.kind, ProbeKind::EchoReply);
+        assert_eq!(outcome.packet.get_payload(), b"payload");
+
+        let seq = PingSequence(NonZeroU16::new(2).unwrap());
+        let outcome = pinger.probe(seq, b"payload").await.unwrap();
+        assert_eq!(outcome.kind, ProbeKind::TimeExceeded);
+        assert_eq!(outcome.source, IpAddr::from([127, 0, 0, 1]));
+        assert!(matches!(
+            pinger
+                .ping(PingSequence(NonZeroU16::new(3).unwrap()), b"payload")
+                .await,
+            Err(SurgeError::TimeExceeded { .. })
+        ));
+        assert_eq!(client.pending_count(), 0);
+    }
+
+    // Other platforms expect the IP header in front of the ICMP message.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[tokio::test]
+    async fn ping_retry() {
+        let (peer, client) = crate::Client::test_transport_pair().await;
+        let peer = Arc::new(peer);
+        // Answer the first request after its timeout, from the fifth on none, the others
+        // right away.
+        let (arrivals, mut arrived) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let mut buf = [0; 2048];
+            while let Ok(len) = peer.recv(&mut buf).await {
+                if buf[7] >= 5 {
+                    let _ = arrivals.send(Instant::now());
+                    continue;
+                }
+                buf[0] = 0;
+                let reply = buf[..len].to_vec();
+                let delay = if reply[7] == 1 { 150 } else { 0 };
+                let peer = peer.clone();
+                tokio::spawn(async move {
+                    time::sleep(Duration::from_millis(delay)).await;
+                    let _ = peer.send(&reply).await;
+                });
+            }
+        });
+
+        let pinger = client
+            .pinger("127.0.0.1".parse().unwrap(), PingIdentifier(1))
+            .await;
+        let (packet, _) = pinger
+            .ping_retry(b"payload", 3, Duration::from_millis(100), Duration::ZERO)
+            .await
+            .unwrap();
+        assert_eq!(packet.get_sequence().into_u16(), 2);
+        assert_eq!(client.pending_count(), 0);
+
+        // Only timeouts are retried.
+        let seq = PingSequence(NonZeroU16::new(3).unwrap());
+        let _waiter = pinger.register(seq, None).unwrap();
+        assert!(matches!(
+            pinger
+                .ping_retry(b"payload", 3, Duration::from_millis(100), Duration::ZERO)
+                .await,
+            Err(SurgeError::IdenticalRequests { .. })
+        ));
+        assert_eq!(pinger.next_sequence().into_u16(), 4);
+
+        // The wait between attempts doubles.
+        let (timeout, backoff) = (Duration::from_millis(50), Duration::from_millis(50));
+        assert!(matches!(
+            pinger.ping_retry(b"payload", 3, timeout, backoff).await,
+            Err(SurgeError::Timeout { .. })
+        ));
+        let mut sent = Vec::new();
+        while let Ok(at) = arrived.try_recv() {
+            sent.push(at);
+        }
+        assert_eq!(sent.len(), 3);
+        let (first, second) = (sent[1] - sent[0], sent[2] - sent[1]);
+        assert!(first >= timeout + backoff, "{:?}", first);
+        assert!(second >= timeout + 2 * backoff, "{:?}", second);
+        assert!(second > first);
+    }
+
+    // Other platforms expect the IP header in front of the ICMP message.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[tokio::test]
+    async fn collectors_leave_other_replies() {
+        let client = crate::Client::test_echo(|_| true).await;
+        let broadcast = client
+            .pinger("127.255.255.255".parse().unwrap(), PingIdentifier(1))
+            .await;
+        let unicast = client
+            .pinger("127.0.0.1".parse().unwrap(), PingIdentifier(2))
+            .await;
+        // Both requests have sequence number 1, and the replies the same source.
+        let (responders, reply) = tokio::join!(
+            broadcast.ping_broadcast(b"payload", Duration::from_millis(200)),
+            async {
+                time::sleep(Duration::from_millis(50)).await;
+                unicast
+                    .ping_timeout(
+                        PingSequence(NonZeroU16::MIN),
+                        b"payload",
+                        Duration::from_millis(100),
+                    )
+                    .await
+            }
+        );
+        assert!(reply.is_ok());
+        assert_eq!(responders.unwrap().len(), 1);
+
+        let multicast = client
+            .pinger("224.0.0.1".parse().unwrap(), PingIdentifier(3))
+            .await;
+        let (responders, reply) = tokio::join!(
+            multicast.ping_multicast(b"payload", Duration::from_millis(200)),
+            async {
+                time::sleep(Duration::from_millis(50)).await;
+                unicast
+                    .ping_timeout(
+                        PingSequence(NonZeroU16::MIN),
+                        b"payload",
+                        Duration::from_millis(100),
+                    )
+                    .await
+            }
+        );
+        assert!(reply.is_ok());
+        assert_eq!(responders.unwrap().len(), 1);
+        assert_eq!(client.pending_count(), 0);
+    }
+
+    // Other platforms expect the IP header in front of the ICMP message.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[tokio::test]
+    async fn reset_leaves_other_pingers() {
+        let client = crate::Client::test_echo(|_| true).await;
+        let host = "127.0.0.1".parse().unwrap();
+        let first = client.pinger(host, PingIdentifier(1)).await;
+        let second = client.pinger(host, PingIdentifier(2)).await;
+        let seq = PingSequence(NonZeroU16::MIN);
+        let (send_time, waiter) = first.ping_send(seq, b"payload").await.unwrap();
+        first.ping_recv(send_time, waiter).await.unwrap();
+        assert!(first.outstanding.lock().is_empty());
+
+        // Without identifiers, the request of `second` is the same as the one of `first`.
+        let _waiter = second.register(seq, None).unwrap();
+        first.reset();
+        assert!(!first.cancel(seq));
+        assert_eq!(client.pending_count(), 1);
+    }
+
+    // Other platforms expect the IP header in front of the ICMP message.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[tokio::test]
+    async fn drop_leaves_other_pingers() {
+        let client = crate::Client::test_echo(|_| true).await;
+        let host = "127.0.0.1".parse().unwrap();
+        let first = client.pinger(host, PingIdentifier(1)).await;
+        let second = client.pinger(host, PingIdentifier(2)).await;
+        assert_eq!(first.ident, None);
+        first
+            .ping(PingSequence(NonZeroU16::MIN), b"payload")
+            .await
+            .unwrap();
+        let seq = PingSequence(NonZeroU16::new(2).unwrap());
+        let (_, late) = first.ping_send(seq, b"payload").await.unwrap();
+        drop(late);
+
+        // Both sequence numbers are now free for `second`, which `first` must leave alone.
+        let _waiters = [
+            second
+                .register(PingSequence(NonZeroU16::MIN), None)
+                .unwrap(),
+            second.register(seq, None).unwrap(),
+        ];
+        let _own = first
+            .register(PingSequence(NonZeroU16::new(3).unwrap()), None)
+            .unwrap();
+        assert_eq!(client.pending_count(), 3);
+        drop(first);
+        assert_eq!(client.pending_count(), 2);
+    }
+}
@@ -0,0 +1,112 @@
+use std::ops::{Deref, DerefMut};
+
+use parking_lot::Mutex;
+
+/// Smallest size class, 64 bytes: the 8 bytes of header and the default payload fit.
+const MIN_CLASS_SHIFT: u32 = 6;
+/// Largest size class, 64 KiB: an ICMP message cannot be larger.
+const MAX_CLASS_SHIFT: u32 = 16;
+const CLASSES: usize = (MAX_CLASS_SHIFT - MIN_CLASS_SHIFT + 1) as usize;
+/// Buffers kept per size class, enough for the requests a client sends in a burst.
+const MAX_FREE_PER_CLASS: usize = 64;
+
+/// Buffers reused to build the packets the pingers of a `Client` send, instead of
+/// allocating one per packet. Buffers are sorted by capacity, rounded up to a power of two.
+#[derive(Debug, Default)]
+pub(crate) struct SendPool {
+    classes: [Mutex<Vec<Vec<u8>>>; CLASSES],
+}
+
+impl SendPool {
+    fn class(len: usize) -> Option<usize> {
+        let shift = len
+            .next_power_of_two()
+            .trailing_zeros()
+            .max(MIN_CLASS_SHIFT);
+        (shift <= MAX_CLASS_SHIFT).then(|| (shift - MIN_CLASS_SHIFT) as usize)
+    }
+
+    fn take(&self, len: usize) -> Vec<u8> {
+        let mut buf = Self::class(len)
+            .and_then(|class| self.classes[class].lock().pop())
+            .unwrap_or_else(|| {
+                Vec::with_capacity(len.next_power_of_two().max(1 << MIN_CLASS_SHIFT))
+            });
+        buf.resize(len, 0);
+        buf
+    }
+
+    fn give(&self, mut buf: Vec<u8>) {
+        if let Some(class) = Self::class(buf.capacity()) {
+            let mut free = self.classes[class].lock();
+            if free.len() < MAX_FREE_PER_CLASS {
+                buf.clear();
+                free.push(buf);
+            }
+        }
+    }
+}
+
+/// A zeroed packet buffer, handed back to its pool, if it comes from one, when dropped.
+pub(crate) struct PacketBuf<'a> {
+    buf: Vec<u8>,
+    pool: Option<&'a SendPool>,
+}
+
+impl<'a> PacketBuf<'a> {
+    pub(crate) fn new(pool: Option<&'a SendPool>, len: usize) -> Self {
+        let buf = match pool {
+            Some(pool) => pool.take(len),
+            None => vec![0; len],
+        };
+        PacketBuf { buf, pool }
+    }
+}
+
+impl Deref for PacketBuf<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+impl DerefMut for PacketBuf<'_> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.buf
+    }
+}
+
+impl Drop for PacketBuf<'_> {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool {
+            pool.give(std::mem::take(&mut self.buf));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuse() {
+        let pool = SendPool::default();
+        let mut buf = PacketBuf::new(Some(&pool), 64);
+        buf[0] = 0xff;
+        let ptr = buf.as_ptr();
+        drop(buf);
+
+        // Same size class, zeroed again.
+        let buf = PacketBuf::new(Some(&pool), 40);
+        assert_eq!(buf.as_ptr(), ptr);
+        assert_eq!(&buf[..], &[0; 40][..]);
+        // Another size class.
+        assert_ne!(PacketBuf::new(Some(&pool), 100).as_ptr(), ptr);
+        drop(buf);
+
+        // Too large for any class: not kept.
+        drop(PacketBuf::new(Some(&pool), 70_000));
+        assert!(pool.classes.iter().all(|free| free.lock().len() <= 1));
+    }
+}
@@ -0,0 +1,75 @@
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use tokio::time;
+
+/// Token bucket capping the rate at which the pingers of a `Client` send packets.
+///
+/// The bucket holds at most a tenth of a second worth of tokens (and at least one), so
+/// short bursts are allowed but the rate over any second stays close to the ceiling.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    state: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// Negative when tokens were reserved by senders that are still waiting.
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    /// A limiter allowing `pps` packets per second, which must not be 0.
+    pub(crate) fn new(pps: u32) -> Self {
+        let rate = f64::from(pps);
+        let burst = (rate / 10.0).max(1.0);
+        RateLimiter {
+            rate,
+            burst,
+            state: Mutex::new(Bucket {
+                tokens: burst,
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Take a token, waiting for one to be available if the bucket is empty.
+    pub(crate) async fn acquire(&self) {
+        let wait = {
+            let mut bucket = self.state.lock();
+            let now = Instant::now();
+            let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+            bucket.refilled_at = now;
+            // Reserve the token right away so that concurrent senders queue up behind us.
+            bucket.tokens -= 1.0;
+            if bucket.tokens >= 0.0 {
+                return;
+            }
+            Duration::from_secs_f64(-bucket.tokens / self.rate)
+        };
+        time::sleep(wait).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn limits_rate_after_burst() {
+        let limiter = RateLimiter::new(100);
+        let start = Instant::now();
+        for _ in 0..10 {
+            limiter.acquire().await;
+        }
+        assert!(start.elapsed() < Duration::from_millis(50));
+        for _ in 0..10 {
+            limiter.acquire().await;
+        }
+        assert!(start.elapsed() >= Duration::from_millis(90));
+    }
+}
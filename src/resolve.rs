@@ -0,0 +1,90 @@
+//! Host name resolution, pluggable for environments where the system resolver is not the
+//! authoritative one.
+
+use std::{future::Future, io, net::SocketAddr};
+
+use futures::future::{BoxFuture, FutureExt};
+
+use crate::ICMP;
+
+/// Resolves a host name, or an address literal, to the addresses it may be pinged at. The
+/// port of the addresses is ignored; the scope id of IPv6 link-local addresses is kept.
+///
+/// Implemented by [`TokioResolver`], the system resolver, and by closures taking the host
+/// name:
+///
+/// ```rust ignore
+/// let resolver = |host: String| async move {
+///     my_dns::lookup(&host).await.map(|ips| ips.into_iter().map(|ip| (ip, 0).into()).collect())
+/// };
+/// let addr = surge_ping::resolve(&resolver, "example.com", ICMP::V4).await?;
+/// ```
+pub trait Resolver: Send + Sync {
+    fn resolve(&self, host: &str) -> BoxFuture<'static, io::Result<Vec<SocketAddr>>>;
+}
+
+/// The system resolver, through `tokio::net::lookup_host`. Understands `fe80::1%eth0`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TokioResolver;
+
+impl Resolver for TokioResolver {
+    fn resolve(&self, host: &str) -> BoxFuture<'static, io::Result<Vec<SocketAddr>>> {
+        let host = format!("{host}:0");
+        async move { Ok(tokio::net::lookup_host(host).await?.collect()) }.boxed()
+    }
+}
+
+impl<F, Fut> Resolver for F
+where
+    F: Fn(String) -> Fut + Send + Sync,
+    Fut: Future<Output = io::Result<Vec<SocketAddr>>> + Send + 'static,
+{
+    fn resolve(&self, host: &str) -> BoxFuture<'static, io::Result<Vec<SocketAddr>>> {
+        self(host.to_string()).boxed()
+    }
+}
+
+/// The first address of `host` a `kind` client can ping, as resolved by `resolver`.
+pub async fn resolve(resolver: &dyn Resolver, host: &str, kind: ICMP) -> io::Result<SocketAddr> {
+    resolver
+        .resolve(host)
+        .await?
+        .into_iter()
+        .find(|addr| addr.is_ipv6() == matches!(kind, ICMP::V6))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no {kind:?} address found for {host}"),
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn custom_resolver() {
+        let resolver = |host: String| async move {
+            match host.as_str() {
+                "dual.test" => Ok(vec![
+                    "[2001:db8::1]:0".parse().unwrap(),
+                    "192.0.2.1:0".parse().unwrap(),
+                ]),
+                _ => Err(io::ErrorKind::NotFound.into()),
+            }
+        };
+        assert_eq!(
+            resolve(&resolver, "dual.test", ICMP::V4).await.unwrap(),
+            "192.0.2.1:0".parse().unwrap()
+        );
+        assert_eq!(
+            resolve(&resolver, "dual.test", ICMP::V6).await.unwrap(),
+            "[2001:db8::1]:0".parse().unwrap()
+        );
+        assert!(resolve(&resolver, "other.test", ICMP::V4).await.is_err());
+        assert!(resolve(&TokioResolver, "127.0.0.1", ICMP::V6)
+            .await
+            .is_err());
+    }
+}
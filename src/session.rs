@@ -0,0 +1,159 @@
+use std::{net::IpAddr, num::NonZeroU16, time::Duration};
+
+use futures::stream::{FuturesUnordered, StreamExt};
+use rand::random;
+use tokio::time::{self, MissedTickBehavior};
+
+use crate::{Client, PingIdentifier, PingSequence, PingStats};
+
+/// The usual "ping a host N times and report" loop: requests are sent every `interval`
+/// without waiting for the previous replies, each waits at most `timeout` for its reply.
+/// With `Config::builder().adaptive()`, a request is sent once the previous one completes
+/// instead, and no sooner than `interval` after it.
+pub struct PingSession;
+
+impl PingSession {
+    /// Size of the payload of the requests, like `ping`.
+    pub const PAYLOAD_SIZE: usize = 56;
+
+    /// Send `count` echo requests to `target` and gather the results. The requests which
+    /// fail or go unanswered are recorded as lost, in [`PingStats::probes`] in the order
+    /// they were sent.
+    pub async fn run(
+        client: &Client,
+        target: IpAddr,
+        count: usize,
+        interval: Duration,
+        timeout: Duration,
+    ) -> PingStats {
+        Self::run_with_progress(client, target, count, interval, timeout, |_| {}).await
+    }
+
+    /// Like [`run`](Self::run), calling `on_progress` with the results so far each time a
+    /// request is answered or given up on, e.g. to refresh a display. The requests are
+    /// recorded in the order they complete in these running statistics.
+    pub async fn run_with_progress<F>(
+        client: &Client,
+        target: IpAddr,
+        count: usize,
+        interval: Duration,
+        timeout: Duration,
+        on_progress: F,
+    ) -> PingStats
+    where
+        F: FnMut(&PingStats),
+    {
+        Self::run_inner(client, target, count, 0, interval, timeout, on_progress).await
+    }
+
+    /// Like [`run`](Self::run), recording the first `warmup` of the `count` requests with
+    /// [`PingStats::record_warmup`]: they count for the loss, but not for the round trip
+    /// times, which the first requests may inflate with address resolution.
+    pub async fn run_with_warmup(
+        client: &Client,
+        target: IpAddr,
+        count: usize,
+        warmup: usize,
+        interval: Duration,
+        timeout: Duration,
+    ) -> PingStats {
+        Self::run_inner(client, target, count, warmup, interval, timeout, |_| {}).await
+    }
+
+    async fn run_inner<F>(
+        client: &Client,
+        target: IpAddr,
+        count: usize,
+        warmup: usize,
+        interval: Duration,
+        timeout: Duration,
+        mut on_progress: F,
+    ) -> PingStats
+    where
+        F: FnMut(&PingStats),
+    {
+        let pinger = client.pinger(target, PingIdentifier(random())).await;
+        let payload = [0; Self::PAYLOAD_SIZE];
+        let mut ticker = time::interval(interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut results = vec![None; count];
+        let mut in_flight = FuturesUnordered::new();
+        let mut seq = PingSequence(NonZeroU16::MIN);
+        let mut running = PingStats::new();
+        let mut complete = |results: &mut Vec<Option<Duration>>, index: usize, rtt| {
+            results[index] = rtt;
+            if index < warmup {
+                running.record_warmup(rtt);
+            } else {
+                running.record(rtt);
+            }
+            on_progress(&running);
+        };
+
+        let adaptive = pinger.is_adaptive();
+
+        for index in 0..count {
+            // Let the replies in while waiting to send the next request.
+            let mut ticked = false;
+            while !ticked || (adaptive && !in_flight.is_empty()) {
+                tokio::select! {
+                    _ = ticker.tick(), if !ticked => ticked = true,
+                    Some((index, rtt)) = in_flight.next() => complete(&mut results, index, rtt),
+                }
+            }
+            if adaptive {
+                // The interval counts from this request.
+                ticker.reset();
+            }
+            let (pinger, payload) = (&pinger, &payload);
+            in_flight.push(async move {
+                let rtt = pinger.ping_timeout(seq, payload, timeout).await;
+                (index, rtt.ok().map(|(_, rtt)| rtt))
+            });
+            seq = seq.next();
+        }
+        while let Some((index, rtt)) = in_flight.next().await {
+            complete(&mut results, index, rtt);
+        }
+
+        let mut stats = PingStats::new();
+        for (index, rtt) in results.into_iter().enumerate() {
+            if index < warmup {
+                stats.record_warmup(rtt);
+            } else {
+                stats.record(rtt);
+            }
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Other platforms expect the IP header in front of the ICMP message.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    #[tokio::test]
+    async fn run() {
+        // Answer every request but the second one.
+        let client = Client::test_echo(|request| request[7] != 2).await;
+        let mut progress = Vec::new();
+        let stats = PingSession::run_with_progress(
+            &client,
+            IpAddr::from([127, 0, 0, 1]),
+            4,
+            Duration::from_millis(10),
+            Duration::from_millis(200),
+            |running| progress.push((running.transmitted, running.received)),
+        )
+        .await;
+        // The lost request completes last, on its timeout.
+        assert_eq!(progress, [(1, 1), (2, 2), (3, 3), (4, 3)]);
+        assert_eq!(stats.transmitted, 4);
+        assert_eq!(stats.received, 3);
+        assert_eq!(stats.probes.len(), 4);
+        assert!(stats.probes[1].is_none());
+        assert!(stats.jitter().is_some());
+    }
+}
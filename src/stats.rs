@@ -0,0 +1,440 @@
+use std::time::Duration;
+
+/// Rank, from 1, of the `percentile` of `count` sorted values by the nearest-rank method.
+fn nearest_rank(percentile: f64, count: u64) -> u64 {
+    ((percentile.clamp(0.0, 100.0) / 100.0 * count as f64).ceil() as u64).max(1)
+}
+
+/// Aggregated results of a series of pings to one host.
+#[derive(Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PingStats {
+    /// Number of echo requests sent.
+    pub transmitted: usize,
+    /// Number of echo replies received.
+    pub received: usize,
+    /// Round trip time of every reply, in the order they were received.
+    pub durations: Vec<Duration>,
+    /// Round trip time of every request in the order they were recorded, `None` if it
+    /// went unanswered.
+    pub probes: Vec<Option<Duration>>,
+    /// Number of consecutive unanswered requests, up to the last one.
+    pub current_loss_run: usize,
+    /// Longest run of consecutive unanswered requests.
+    pub max_loss_run: usize,
+}
+
+impl PingStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for one request, `None` if it went unanswered.
+    pub fn record(&mut self, rtt: Option<Duration>) {
+        self.record_warmup(rtt);
+        if let Some(rtt) = rtt {
+            self.durations.push(rtt);
+        }
+    }
+
+    /// Account for one warmup request, e.g. one delayed by ARP or neighbor discovery: it
+    /// counts for the loss, but its round trip time is left out of [`durations`](Self::durations)
+    /// and of the figures derived from them.
+    pub fn record_warmup(&mut self, rtt: Option<Duration>) {
+        self.transmitted += 1;
+        self.probes.push(rtt);
+        match rtt {
+            Some(_) => {
+                self.received += 1;
+                self.current_loss_run = 0;
+            }
+            None => {
+                self.current_loss_run += 1;
+                self.max_loss_run = self.max_loss_run.max(self.current_loss_run);
+            }
+        }
+    }
+
+    /// Fraction of requests which went unanswered, between 0.0 and 1.0.
+    pub fn loss(&self) -> f64 {
+        if self.transmitted == 0 {
+            return 0.0;
+        }
+        1.0 - self.received as f64 / self.transmitted as f64
+    }
+
+    pub fn min(&self) -> Option<Duration> {
+        self.durations.iter().min().copied()
+    }
+
+    pub fn max(&self) -> Option<Duration> {
+        self.durations.iter().max().copied()
+    }
+
+    pub fn avg(&self) -> Option<Duration> {
+        let sum: Duration = self.durations.iter().sum();
+        sum.checked_div(self.durations.len() as u32)
+    }
+
+    /// Mean absolute difference between consecutive round trip times, `None` with fewer
+    /// than two replies.
+    pub fn jitter(&self) -> Option<Duration> {
+        let sum: Duration = self
+            .durations
+            .windows(2)
+            .map(|pair| pair[1].abs_diff(pair[0]))
+            .sum();
+        sum.checked_div(self.durations.len().checked_sub(1)? as u32)
+    }
+
+    /// Round trip time below which `percentile` percent of them fall, e.g. 99.0 for the
+    /// 99th percentile, by the nearest-rank method. Sorts a copy of the round trip times,
+    /// see [`Stats`] for long runs.
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        let mut durations = self.durations.clone();
+        durations.sort_unstable();
+        let rank = nearest_rank(percentile, durations.len() as u64);
+        durations.get(rank as usize - 1).copied()
+    }
+
+    /// Longest burst of consecutive lost requests.
+    pub fn longest_loss_run(&self) -> usize {
+        self.max_loss_run
+    }
+
+    /// The figures derived from these results, see [`PingSummary`].
+    pub fn summary(&self) -> PingSummary {
+        let mut sorted = self.durations.clone();
+        sorted.sort_unstable();
+        let percentile = |percentile| {
+            let rank = nearest_rank(percentile, sorted.len() as u64);
+            sorted.get(rank as usize - 1).copied()
+        };
+        PingSummary {
+            transmitted: self.transmitted,
+            received: self.received,
+            loss: self.loss(),
+            min: self.min(),
+            avg: self.avg(),
+            max: self.max(),
+            stddev: self.stddev(),
+            jitter: self.jitter(),
+            p50: percentile(50.0),
+            p90: percentile(90.0),
+            p95: percentile(95.0),
+            p99: percentile(99.0),
+            longest_loss_run: self.longest_loss_run(),
+            rtts: self.durations.clone(),
+        }
+    }
+
+    /// Population standard deviation of the round trip times.
+    pub fn stddev(&self) -> Option<Duration> {
+        let avg = self.avg()?.as_secs_f64();
+        let variance = self
+            .durations
+            .iter()
+            .map(|rtt| (rtt.as_secs_f64() - avg).powi(2))
+            .sum::<f64>()
+            / self.durations.len() as f64;
+        Some(Duration::from_secs_f64(variance.sqrt()))
+    }
+}
+
+/// Snapshot of [`PingStats`] with the derived figures computed, e.g. for serialization.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PingSummary {
+    pub transmitted: usize,
+    pub received: usize,
+    /// Fraction of requests which went unanswered, between 0.0 and 1.0.
+    pub loss: f64,
+    pub min: Option<Duration>,
+    pub avg: Option<Duration>,
+    pub max: Option<Duration>,
+    pub stddev: Option<Duration>,
+    /// Mean absolute difference between consecutive round trip times.
+    pub jitter: Option<Duration>,
+    /// Percentiles of the round trip times, see [`PingStats::percentile`].
+    pub p50: Option<Duration>,
+    pub p90: Option<Duration>,
+    pub p95: Option<Duration>,
+    pub p99: Option<Duration>,
+    /// Longest burst of consecutive lost requests.
+    pub longest_loss_run: usize,
+    /// Round trip time of every reply, in the order they were received.
+    pub rtts: Vec<Duration>,
+}
+
+/// Sub-buckets per power of two of the histogram of [`Stats`]: a percentile is off by at
+/// most 2^(1/16) - 1, about 4.4%, before being reported at the middle of its bucket.
+const SUB_BUCKETS: u32 = 16;
+/// Round trip times up to 2^40 ns, about 18 minutes, larger ones share the last bucket.
+const BUCKETS: usize = 40 * SUB_BUCKETS as usize;
+
+/// Running statistics of round trip times in constant memory, for runs too long to keep
+/// every round trip time as [`PingStats`] does. The mean and variance are exact (Welford's
+/// algorithm), the percentiles come from a logarithmic histogram. Unanswered requests may
+/// be accounted for too, for the loss figures of [`PingStats`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Stats {
+    transmitted: u64,
+    received: u64,
+    current_loss_run: u64,
+    max_loss_run: u64,
+    count: u64,
+    /// In seconds.
+    mean: f64,
+    /// Sum of the squared differences to the mean, in seconds squared.
+    m2: f64,
+    min: Option<Duration>,
+    max: Option<Duration>,
+    /// Round trip time of the last reply, and sum of the absolute differences between
+    /// consecutive ones in seconds.
+    last: Option<Duration>,
+    jitter_sum: f64,
+    buckets: Vec<u64>,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Stats {
+            transmitted: 0,
+            received: 0,
+            current_loss_run: 0,
+            max_loss_run: 0,
+            count: 0,
+            mean: 0.0,
+            m2: 0.0,
+            min: None,
+            max: None,
+            last: None,
+            jitter_sum: 0.0,
+            buckets: vec![0; BUCKETS],
+        }
+    }
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Account for the round trip time of one reply.
+    pub fn record(&mut self, rtt: Duration) {
+        self.record_warmup(Some(rtt));
+        self.count += 1;
+        let secs = rtt.as_secs_f64();
+        let delta = secs - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (secs - self.mean);
+        self.min = Some(self.min.map_or(rtt, |min| min.min(rtt)));
+        self.max = Some(self.max.map_or(rtt, |max| max.max(rtt)));
+        if let Some(last) = self.last.replace(rtt) {
+            self.jitter_sum += rtt.abs_diff(last).as_secs_f64();
+        }
+        self.buckets[Self::bucket(rtt)] += 1;
+    }
+
+    /// Account for one unanswered request.
+    pub fn record_loss(&mut self) {
+        self.record_warmup(None);
+    }
+
+    /// Account for one warmup request like [`PingStats::record_warmup`]: it counts for the
+    /// loss, but its round trip time is left out of the figures derived from them.
+    pub fn record_warmup(&mut self, rtt: Option<Duration>) {
+        self.transmitted += 1;
+        match rtt {
+            Some(_) => {
+                self.received += 1;
+                self.current_loss_run = 0;
+            }
+            None => {
+                self.current_loss_run += 1;
+                self.max_loss_run = self.max_loss_run.max(self.current_loss_run);
+            }
+        }
+    }
+
+    fn bucket(rtt: Duration) -> usize {
+        let nanos = rtt.as_nanos().max(1) as f64;
+        ((nanos.log2() * f64::from(SUB_BUCKETS)) as usize).min(BUCKETS - 1)
+    }
+
+    /// Number of round trip times recorded.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Number of requests accounted for, answered or not.
+    pub fn transmitted(&self) -> u64 {
+        self.transmitted
+    }
+
+    /// Number of replies accounted for, warmup ones included.
+    pub fn received(&self) -> u64 {
+        self.received
+    }
+
+    /// Fraction of requests which went unanswered, between 0.0 and 1.0.
+    pub fn loss(&self) -> f64 {
+        if self.transmitted == 0 {
+            return 0.0;
+        }
+        1.0 - self.received as f64 / self.transmitted as f64
+    }
+
+    /// Longest burst of consecutive lost requests.
+    pub fn longest_loss_run(&self) -> u64 {
+        self.max_loss_run
+    }
+
+    pub fn min(&self) -> Option<Duration> {
+        self.min
+    }
+
+    pub fn max(&self) -> Option<Duration> {
+        self.max
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        (self.count > 0).then(|| Duration::from_secs_f64(self.mean))
+    }
+
+    /// Sum of the round trip times.
+    pub fn sum(&self) -> Duration {
+        Duration::from_secs_f64(self.mean * self.count as f64)
+    }
+
+    /// Mean absolute difference between consecutive round trip times, `None` with fewer
+    /// than two of them.
+    pub fn jitter(&self) -> Option<Duration> {
+        (self.count > 1).then(|| Duration::from_secs_f64(self.jitter_sum / (self.count - 1) as f64))
+    }
+
+    /// Population standard deviation of the round trip times.
+    pub fn stddev(&self) -> Option<Duration> {
+        (self.count > 0).then(|| Duration::from_secs_f64((self.m2 / self.count as f64).sqrt()))
+    }
+
+    /// Approximate round trip time below which `percentile` percent of them fall, e.g. 99.0
+    /// for the 99th percentile. Within about 2.2% of the exact value.
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        let (min, max) = (self.min?, self.max?);
+        let rank = nearest_rank(percentile, self.count);
+        let mut seen = 0;
+        let bucket = self.buckets.iter().position(|&count| {
+            seen += count;
+            seen >= rank
+        })?;
+        let middle = (bucket as f64 + 0.5) / f64::from(SUB_BUCKETS);
+        Some(Duration::from_nanos(middle.exp2() as u64).clamp(min, max))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jitter_and_loss_runs() {
+        let mut stats = PingStats::new();
+        for rtt in [Some(10), None, None, Some(30), None, Some(20)] {
+            stats.record(rtt.map(Duration::from_millis));
+        }
+        // |30 - 10| and |20 - 30|
+        assert_eq!(stats.jitter(), Some(Duration::from_millis(15)));
+        assert_eq!(stats.longest_loss_run(), 2);
+        assert_eq!(stats.current_loss_run, 0);
+
+        stats.durations.truncate(1);
+        assert_eq!(stats.jitter(), None);
+    }
+
+    #[test]
+    fn warmup() {
+        let mut stats = PingStats::new();
+        stats.record_warmup(Some(Duration::from_millis(900)));
+        stats.record_warmup(None);
+        stats.record(Some(Duration::from_millis(10)));
+        assert_eq!((stats.transmitted, stats.received), (3, 2));
+        assert_eq!(stats.probes.len(), 3);
+        assert_eq!(stats.max(), Some(Duration::from_millis(10)));
+    }
+
+    #[test]
+    fn percentiles() {
+        let mut stats = PingStats::new();
+        assert_eq!(stats.percentile(50.0), None);
+        // Out of order, with a lost request which does not count.
+        for ms in (1..=20).rev() {
+            stats.record(Some(Duration::from_millis(ms)));
+        }
+        stats.record(None);
+        assert_eq!(stats.percentile(50.0), Some(Duration::from_millis(10)));
+        assert_eq!(stats.percentile(0.0), Some(Duration::from_millis(1)));
+        assert_eq!(stats.percentile(100.0), Some(Duration::from_millis(20)));
+
+        let summary = stats.summary();
+        assert_eq!(summary.p90, Some(Duration::from_millis(18)));
+        assert_eq!(summary.p95, Some(Duration::from_millis(19)));
+        assert_eq!(summary.p99, Some(Duration::from_millis(20)));
+    }
+
+    #[test]
+    fn running_stats() {
+        let mut stats = Stats::new();
+        assert_eq!(stats.mean(), None);
+        assert_eq!(stats.percentile(50.0), None);
+
+        let mut all = PingStats::new();
+        for ms in 1..=1000 {
+            stats.record(Duration::from_millis(ms));
+            all.record(Some(Duration::from_millis(ms)));
+        }
+        assert_eq!(stats.count(), 1000);
+        assert_eq!(stats.min(), all.min());
+        assert_eq!(stats.max(), all.max());
+        let close = |got: Duration, want: Duration, tolerance: f64| {
+            (got.as_secs_f64() / want.as_secs_f64() - 1.0).abs() < tolerance
+        };
+        assert!(close(stats.mean().unwrap(), all.avg().unwrap(), 1e-9));
+        assert!(close(stats.stddev().unwrap(), all.stddev().unwrap(), 1e-9));
+        for (percentile, ms) in [(50.0, 500), (90.0, 900), (99.0, 990)] {
+            let got = stats.percentile(percentile).unwrap();
+            assert!(
+                close(got, Duration::from_millis(ms), 0.025),
+                "{}: {:?}",
+                percentile,
+                got
+            );
+        }
+        assert_eq!(stats.percentile(100.0), stats.max());
+    }
+
+    #[test]
+    fn running_jitter_and_loss() {
+        let mut stats = Stats::new();
+        let mut all = PingStats::new();
+        stats.record_warmup(Some(Duration::from_millis(900)));
+        all.record_warmup(Some(Duration::from_millis(900)));
+        for rtt in [Some(10), None, None, Some(30), None, Some(20)] {
+            let rtt = rtt.map(Duration::from_millis);
+            match rtt {
+                Some(rtt) => stats.record(rtt),
+                None => stats.record_loss(),
+            }
+            all.record(rtt);
+        }
+        assert_eq!(stats.jitter(), all.jitter());
+        assert_eq!(stats.longest_loss_run(), all.longest_loss_run() as u64);
+        assert_eq!(stats.loss(), all.loss());
+        assert_eq!(
+            (stats.transmitted(), stats.received(), stats.count()),
+            (7, 4, 3)
+        );
+        assert_eq!(stats.sum(), Duration::from_millis(60));
+    }
+}
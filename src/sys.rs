@@ -0,0 +1,515 @@
+//! Linux specific socket plumbing that is not covered by `socket2`.
+use std::{
+    convert::TryFrom,
+    ffi::CStr,
+    io,
+    mem::{self, MaybeUninit},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    os::unix::io::RawFd,
+    ptr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use socket2::SockAddr;
+
+use crate::client::{PktInfo, RecvMsg};
+
+/// Room for a hop limit, a packet info and a timestamping control message.
+const CMSG_BUF_LEN: usize = 256;
+
+/// Control message buffer, `u64` elements keep it aligned for `cmsghdr`.
+type CmsgBuf = [u64; CMSG_BUF_LEN / 8];
+
+/// Size of the buffer each datagram is received into.
+const RECV_BUF_LEN: usize = 2048;
+
+/// Make `call` again for as long as a signal interrupts it: `EINTR` is not a failure.
+fn retry_interrupted<T>(mut call: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    loop {
+        match call() {
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            result => return result,
+        }
+    }
+}
+
+fn setsockopt<T>(fd: RawFd, level: libc::c_int, name: libc::c_int, value: T) -> io::Result<()> {
+    let ret = unsafe {
+        libc::setsockopt(
+            fd,
+            level,
+            name,
+            &value as *const T as *const libc::c_void,
+            mem::size_of::<T>() as libc::socklen_t,
+        )
+    };
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Set the queueing priority of the packets sent on the socket.
+pub(crate) fn set_priority(fd: RawFd, priority: u32) -> io::Result<()> {
+    setsockopt(
+        fd,
+        libc::SOL_SOCKET,
+        libc::SO_PRIORITY,
+        priority as libc::c_int,
+    )
+}
+
+/// Ask the kernel to report the TTL of received IPv4 packets in a control message.
+pub(crate) fn set_recv_ttl(fd: RawFd) -> io::Result<()> {
+    setsockopt(fd, libc::IPPROTO_IP, libc::IP_RECVTTL, 1 as libc::c_int)
+}
+
+/// Ask the kernel to timestamp received packets in a control message: hardware timestamps
+/// with a software fallback through `SO_TIMESTAMPING`, or software ones through
+/// `SO_TIMESTAMPNS`. Returns whether either is enabled.
+pub(crate) fn set_recv_timestamps(fd: RawFd) -> bool {
+    let flags = libc::SOF_TIMESTAMPING_RX_HARDWARE
+        | libc::SOF_TIMESTAMPING_RAW_HARDWARE
+        | libc::SOF_TIMESTAMPING_RX_SOFTWARE
+        | libc::SOF_TIMESTAMPING_SOFTWARE;
+    setsockopt(
+        fd,
+        libc::SOL_SOCKET,
+        libc::SO_TIMESTAMPING,
+        flags as libc::c_int,
+    )
+    .is_ok()
+        || setsockopt(fd, libc::SOL_SOCKET, libc::SO_TIMESTAMPNS, 1 as libc::c_int).is_ok()
+}
+
+/// Read a `timespec` timestamp, `None` if unset.
+fn timespec_time(ts: &libc::timespec) -> Option<SystemTime> {
+    if ts.tv_sec == 0 && ts.tv_nsec == 0 {
+        return None;
+    }
+    let since_epoch = Duration::new(u64::try_from(ts.tv_sec).ok()?, ts.tv_nsec as u32);
+    UNIX_EPOCH.checked_add(since_epoch)
+}
+
+/// Ask the kernel to report the hop limit of received IPv6 packets in a control message.
+pub(crate) fn set_recv_hop_limit(fd: RawFd) -> io::Result<()> {
+    setsockopt(
+        fd,
+        libc::IPPROTO_IPV6,
+        libc::IPV6_RECVHOPLIMIT,
+        1 as libc::c_int,
+    )
+}
+
+/// Ask the kernel to report the destination address of received IPv6 packets in a
+/// control message.
+pub(crate) fn set_recv_pktinfo_v6(fd: RawFd) -> io::Result<()> {
+    setsockopt(
+        fd,
+        libc::IPPROTO_IPV6,
+        libc::IPV6_RECVPKTINFO,
+        1 as libc::c_int,
+    )
+}
+
+/// `ICMP_FILTER` option of the `SOL_RAW` level, missing from `libc`.
+const ICMP_FILTER: libc::c_int = 1;
+/// `ICMP6_FILTER` option of the `IPPROTO_ICMPV6` level, missing from `libc`.
+const ICMP6_FILTER: libc::c_int = 1;
+
+/// Only deliver the ICMP messages of the given types on a raw IPv4 socket. The filter is a
+/// mask of the types 0 to 31 to block.
+pub(crate) fn set_icmp_filter(fd: RawFd, types: &[u8]) -> io::Result<()> {
+    let pass = types
+        .iter()
+        .filter(|&&icmp_type| icmp_type < 32)
+        .fold(0u32, |mask, &icmp_type| mask | 1 << icmp_type);
+    setsockopt(fd, libc::SOL_RAW, ICMP_FILTER, !pass)
+}
+
+/// Only deliver the ICMPv6 messages of the given types on a raw IPv6 socket. The filter is
+/// a mask of the 256 types to block.
+pub(crate) fn set_icmp6_filter(fd: RawFd, types: &[u8]) -> io::Result<()> {
+    let mut block = [u32::MAX; 8];
+    for &icmp_type in types {
+        block[usize::from(icmp_type >> 5)] &= !(1 << (icmp_type & 31));
+    }
+    setsockopt(fd, libc::IPPROTO_ICMPV6, ICMP6_FILTER, block)
+}
+
+/// `struct in6_flowlabel_req`, missing from `libc`.
+#[repr(C)]
+struct In6FlowlabelReq {
+    dst: libc::in6_addr,
+    /// In network byte order.
+    label: u32,
+    action: u8,
+    share: u8,
+    flags: u16,
+    expires: u16,
+    linger: u16,
+    pad: u32,
+}
+
+const IPV6_FL_A_GET: u8 = 0;
+const IPV6_FL_F_CREATE: u16 = 1;
+const IPV6_FL_S_ANY: u8 = 255;
+
+/// Send the IPv6 flow label given as flow information of the destination addresses. The
+/// kernel only sends labels leased by the socket, so a shared lease of `label` is taken
+/// first; 0, no label, needs none.
+pub(crate) fn set_flow_label(fd: RawFd, label: u32) -> io::Result<()> {
+    if label != 0 {
+        let req = In6FlowlabelReq {
+            // A lease needs a destination, but the kernel only compares it with the one of
+            // other leases of the label, not with where packets go: use the same for all.
+            dst: libc::in6_addr {
+                s6_addr: Ipv6Addr::LOCALHOST.octets(),
+            },
+            label: label.to_be(),
+            action: IPV6_FL_A_GET,
+            share: IPV6_FL_S_ANY,
+            flags: IPV6_FL_F_CREATE,
+            expires: 0,
+            linger: 0,
+            pad: 0,
+        };
+        setsockopt(fd, libc::IPPROTO_IPV6, libc::IPV6_FLOWLABEL_MGR, req)?;
+    }
+    setsockopt(
+        fd,
+        libc::IPPROTO_IPV6,
+        libc::IPV6_FLOWINFO_SEND,
+        1 as libc::c_int,
+    )
+}
+
+/// Set the Don't Fragment bit on outgoing IPv4 packets by forcing path MTU discovery.
+pub(crate) fn set_dont_fragment_v4(fd: RawFd, dont_fragment: bool) -> io::Result<()> {
+    let value = if dont_fragment {
+        libc::IP_PMTUDISC_DO
+    } else {
+        libc::IP_PMTUDISC_DONT
+    };
+    setsockopt(fd, libc::IPPROTO_IP, libc::IP_MTU_DISCOVER, value)
+}
+
+/// Forbid fragmentation of outgoing IPv6 packets by forcing path MTU discovery.
+pub(crate) fn set_dont_fragment_v6(fd: RawFd, dont_fragment: bool) -> io::Result<()> {
+    let value = if dont_fragment {
+        libc::IPV6_PMTUDISC_DO
+    } else {
+        libc::IPV6_PMTUDISC_DONT
+    };
+    setsockopt(fd, libc::IPPROTO_IPV6, libc::IPV6_MTU_DISCOVER, value)
+}
+
+/// Set the IP options of the outgoing IPv4 packets, e.g. Record Route.
+pub(crate) fn set_ip_options(fd: RawFd, options: &[u8]) -> io::Result<()> {
+    let ret = unsafe {
+        libc::setsockopt(
+            fd,
+            libc::IPPROTO_IP,
+            libc::IP_OPTIONS,
+            options.as_ptr() as *const libc::c_void,
+            options.len() as libc::socklen_t,
+        )
+    };
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// The MTU of the network interface `name`, queried through the socket `fd`.
+pub(crate) fn interface_mtu(fd: RawFd, name: &str) -> io::Result<usize> {
+    let mut req: libc::ifreq = unsafe { mem::zeroed() };
+    // Leave room for the terminating NUL.
+    if name.len() >= req.ifr_name.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "interface name too long",
+        ));
+    }
+    for (dst, src) in req.ifr_name.iter_mut().zip(name.bytes()) {
+        *dst = src as libc::c_char;
+    }
+    if unsafe { libc::ioctl(fd, libc::SIOCGIFMTU as _, &mut req) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { req.ifr_ifru.ifru_mtu } as usize)
+}
+
+/// The name of the network interface holding the local address `addr`, if any.
+pub(crate) fn interface_of(addr: IpAddr) -> io::Result<Option<String>> {
+    let mut addrs = ptr::null_mut();
+    if unsafe { libc::getifaddrs(&mut addrs) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut name = None;
+    let mut cur = addrs;
+    while let Some(ifa) = unsafe { cur.as_ref() } {
+        cur = ifa.ifa_next;
+        let ifa_addr = match unsafe { ifa.ifa_addr.as_ref() } {
+            Some(ifa_addr) => ifa_addr,
+            None => continue,
+        };
+        let found = match i32::from(ifa_addr.sa_family) {
+            libc::AF_INET => {
+                let sin = unsafe { &*(ifa.ifa_addr as *const libc::sockaddr_in) };
+                IpAddr::V4(Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr))) == addr
+            }
+            libc::AF_INET6 => {
+                let sin6 = unsafe { &*(ifa.ifa_addr as *const libc::sockaddr_in6) };
+                IpAddr::V6(Ipv6Addr::from(sin6.sin6_addr.s6_addr)) == addr
+            }
+            _ => false,
+        };
+        if found {
+            let ifa_name = unsafe { CStr::from_ptr(ifa.ifa_name) };
+            name = Some(ifa_name.to_string_lossy().into_owned());
+            break;
+        }
+    }
+    unsafe { libc::freeifaddrs(addrs) };
+    Ok(name)
+}
+
+/// Point `msg` at the address, data and control buffers of one datagram.
+fn init_msghdr(
+    msg: &mut libc::msghdr,
+    addr: &mut MaybeUninit<libc::sockaddr_storage>,
+    iov: &mut libc::iovec,
+    cmsg_buf: &mut CmsgBuf,
+) {
+    msg.msg_name = addr.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    msg.msg_iov = iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = cmsg_buf.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = CMSG_BUF_LEN as _;
+}
+
+/// Build the `RecvMsg` of a datagram filled in by `recvmsg(2)` / `recvmmsg(2)`.
+fn parse_msghdr(
+    msg: &libc::msghdr,
+    addr: &MaybeUninit<libc::sockaddr_storage>,
+    size: usize,
+) -> io::Result<RecvMsg> {
+    let addr = unsafe { SockAddr::new(addr.assume_init(), msg.msg_namelen) }
+        .as_socket()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "unexpected address family"))?;
+
+    let mut hop_limit = None;
+    let mut dst_addr = None;
+    let mut kernel_time = None;
+    let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(msg) };
+    while !cmsg.is_null() {
+        let hdr = unsafe { &*cmsg };
+        if (hdr.cmsg_level == libc::IPPROTO_IP && hdr.cmsg_type == libc::IP_TTL)
+            || (hdr.cmsg_level == libc::IPPROTO_IPV6 && hdr.cmsg_type == libc::IPV6_HOPLIMIT)
+        {
+            let value: libc::c_int =
+                unsafe { ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::c_int) };
+            hop_limit = u8::try_from(value).ok();
+        } else if hdr.cmsg_level == libc::IPPROTO_IPV6 && hdr.cmsg_type == libc::IPV6_PKTINFO {
+            let info: libc::in6_pktinfo =
+                unsafe { ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::in6_pktinfo) };
+            dst_addr = Some(Ipv6Addr::from(info.ipi6_addr.s6_addr).into());
+        } else if hdr.cmsg_level == libc::SOL_SOCKET && hdr.cmsg_type == libc::SCM_TIMESTAMPING {
+            // Software, deprecated, then raw hardware timestamp.
+            let ts: [libc::timespec; 3] =
+                unsafe { ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const [libc::timespec; 3]) };
+            kernel_time = timespec_time(&ts[2]).or_else(|| timespec_time(&ts[0]));
+        } else if hdr.cmsg_level == libc::SOL_SOCKET && hdr.cmsg_type == libc::SCM_TIMESTAMPNS {
+            let ts: libc::timespec =
+                unsafe { ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::timespec) };
+            kernel_time = timespec_time(&ts);
+        }
+        cmsg = unsafe { libc::CMSG_NXTHDR(msg, cmsg) };
+    }
+
+    Ok(RecvMsg {
+        size,
+        addr,
+        hop_limit,
+        dst_addr,
+        kernel_time,
+    })
+}
+
+/// Receive a single datagram with `recvmsg(2)`, collecting the TTL / hop limit
+/// control message if the kernel attached one.
+pub(crate) fn recv_msg(fd: RawFd, buf: &mut [u8]) -> io::Result<RecvMsg> {
+    let mut addr = MaybeUninit::zeroed();
+    let mut cmsg_buf: CmsgBuf = Default::default();
+    let mut iov = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    init_msghdr(&mut msg, &mut addr, &mut iov, &mut cmsg_buf);
+
+    let size = retry_interrupted(|| match unsafe { libc::recvmsg(fd, &mut msg, 0) } {
+        -1 => Err(io::Error::last_os_error()),
+        size => Ok(size as usize),
+    })?;
+    parse_msghdr(&msg, &addr, size)
+}
+
+/// Send a datagram to `target` with an `IP_PKTINFO` / `IPV6_PKTINFO` control message
+/// choosing its source address and outgoing interface.
+pub(crate) fn send_to_with_pktinfo(
+    fd: RawFd,
+    buf: &[u8],
+    target: SocketAddr,
+    pktinfo: PktInfo,
+) -> io::Result<usize> {
+    let addr = SockAddr::from(target);
+    let mut iov = libc::iovec {
+        iov_base: buf.as_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+    let mut cmsg_buf: CmsgBuf = Default::default();
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_name = addr.as_ptr() as *mut libc::c_void;
+    msg.msg_namelen = addr.len();
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = cmsg_buf.as_mut_ptr() as *mut libc::c_void;
+
+    match target {
+        SocketAddr::V4(_) => {
+            let source = match pktinfo.source {
+                Some(IpAddr::V4(source)) => source,
+                _ => Ipv4Addr::UNSPECIFIED,
+            };
+            let info = libc::in_pktinfo {
+                ipi_ifindex: pktinfo.if_index as libc::c_int,
+                ipi_spec_dst: libc::in_addr {
+                    s_addr: u32::from(source).to_be(),
+                },
+                ipi_addr: libc::in_addr { s_addr: 0 },
+            };
+            unsafe { write_cmsg(&mut msg, libc::IPPROTO_IP, libc::IP_PKTINFO, info) };
+        }
+        SocketAddr::V6(target) => {
+            let source = match pktinfo.source {
+                Some(IpAddr::V6(source)) => source,
+                _ => Ipv6Addr::UNSPECIFIED,
+            };
+            let if_index = match pktinfo.if_index {
+                // The interface of a link-local target, the routing table picks it otherwise.
+                0 => target.scope_id(),
+                if_index => if_index,
+            };
+            let info = libc::in6_pktinfo {
+                ipi6_addr: libc::in6_addr {
+                    s6_addr: source.octets(),
+                },
+                ipi6_ifindex: if_index,
+            };
+            unsafe { write_cmsg(&mut msg, libc::IPPROTO_IPV6, libc::IPV6_PKTINFO, info) };
+        }
+    }
+
+    let size = unsafe { libc::sendmsg(fd, &msg, 0) };
+    if size == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(size as usize)
+}
+
+/// Write `data` as the only control message of `msg`, whose control buffer must have room
+/// for it.
+unsafe fn write_cmsg<T>(msg: &mut libc::msghdr, level: libc::c_int, ty: libc::c_int, data: T) {
+    msg.msg_controllen = libc::CMSG_SPACE(mem::size_of::<T>() as u32) as _;
+    let cmsg = libc::CMSG_FIRSTHDR(msg);
+    (*cmsg).cmsg_level = level;
+    (*cmsg).cmsg_type = ty;
+    (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<T>() as u32) as _;
+    ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut T, data);
+}
+
+/// Buffers to receive up to `len` datagrams with a single `recvmmsg(2)` call.
+pub(crate) struct RecvBatch {
+    bufs: Vec<[u8; RECV_BUF_LEN]>,
+    addrs: Vec<MaybeUninit<libc::sockaddr_storage>>,
+    cmsg_bufs: Vec<CmsgBuf>,
+    /// The `recvmmsg(2)` arguments, pointing into the buffers above. Allocated once, their
+    /// pointers and lengths are reset before each call.
+    iovs: Vec<libc::iovec>,
+    msgs: Vec<libc::mmsghdr>,
+    /// Index in `bufs` and metadata of each datagram read.
+    received: Vec<(usize, RecvMsg)>,
+}
+
+// The raw pointers of `iovs` and `msgs` only ever point into the buffers of the batch.
+unsafe impl Send for RecvBatch {}
+
+impl RecvBatch {
+    pub(crate) fn new(len: usize) -> Self {
+        Self {
+            bufs: vec![[0; RECV_BUF_LEN]; len],
+            addrs: vec![MaybeUninit::zeroed(); len],
+            cmsg_bufs: vec![Default::default(); len],
+            iovs: vec![
+                libc::iovec {
+                    iov_base: ptr::null_mut(),
+                    iov_len: 0,
+                };
+                len
+            ],
+            msgs: vec![unsafe { mem::zeroed() }; len],
+            received: Vec::with_capacity(len),
+        }
+    }
+
+    /// Read as many datagrams as are queued, up to the batch size, without blocking.
+    pub(crate) fn recv(&mut self, fd: RawFd) -> io::Result<()> {
+        let len = self.bufs.len();
+        for ((((msg, addr), iov), buf), cmsg_buf) in self
+            .msgs
+            .iter_mut()
+            .zip(self.addrs.iter_mut())
+            .zip(self.iovs.iter_mut())
+            .zip(self.bufs.iter_mut())
+            .zip(self.cmsg_bufs.iter_mut())
+        {
+            iov.iov_base = buf.as_mut_ptr() as *mut libc::c_void;
+            iov.iov_len = buf.len();
+            init_msghdr(&mut msg.msg_hdr, addr, iov, cmsg_buf);
+            msg.msg_len = 0;
+        }
+
+        let count = retry_interrupted(|| {
+            match unsafe {
+                libc::recvmmsg(
+                    fd,
+                    self.msgs.as_mut_ptr(),
+                    len as _,
+                    libc::MSG_DONTWAIT,
+                    ptr::null_mut(),
+                )
+            } {
+                -1 => Err(io::Error::last_os_error()),
+                count => Ok(count as usize),
+            }
+        })?;
+
+        self.received.clear();
+        for (idx, (msg, addr)) in self.msgs.iter().zip(&self.addrs).take(count).enumerate() {
+            if let Ok(received) = parse_msghdr(&msg.msg_hdr, addr, msg.msg_len as usize) {
+                self.received.push((idx, received));
+            }
+        }
+        Ok(())
+    }
+
+    /// The datagrams read by the last [`recv`](Self::recv) call, with their data.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&RecvMsg, &[u8])> {
+        self.received
+            .iter()
+            .map(move |(idx, msg)| (msg, &self.bufs[*idx][..msg.size]))
+    }
+}